@@ -1,4 +1,4 @@
-# VERSION 1.1.5
+# VERSION 1.1.10
 
 import sys
 import win32com.client
@@ -10,6 +10,7 @@ from openpyxl.worksheet.datavalidation import DataValidation
 from openpyxl.styles import Alignment, Font, PatternFill
 from openpyxl.formatting.rule import FormulaRule
 
+import os
 from pathlib import Path
 
 
@@ -24,12 +25,14 @@ HEADERS = [
     ("Frequency", 20),
 ]
 
-STATUS_OPTIONS = ["Pass", "Fail", "Pending", "Blocked"]
+STATUS_OPTIONS = ["Pass", "Fail", "Pending", "Blocked", "Not verified", "Skipped"]
 STATUS_COLORS  = {
     "Pass":    "c6efce",
     "Fail":    "ffc7ce",
     "Pending": "ffeb9c",
     "Blocked": "add8e6",
+    "Not verified": "e7e6e6",
+    "Skipped": "d9d9d9",
 }
 
 ## Helpers ##
@@ -152,9 +155,11 @@ def populate_single_cell(ws, title_text):
     ws.column_dimensions["A"].width = 120
     wrap_all_cells(ws)
 
-def update_test_row_by_id(ws, test_id: str, new_status: str, new_notes: str = None):
+def update_test_row_by_id(ws, test_id: str, new_status: str, new_notes: str = None, log_link: str = None, frequency: str = None):
     """
     Update the Status and optionally Notes cell for the row matching the given Test ID.
+    If log_link is given, the Test ID cell links to the test log.
+    If frequency is given, it replaces the Frequency cell, ex: "2/3 passed".
     """
     if new_status not in STATUS_OPTIONS:
         raise ValueError(f"Invalid status '{new_status}'. Must be one of: {STATUS_OPTIONS}")
@@ -167,6 +172,7 @@ def update_test_row_by_id(ws, test_id: str, new_status: str, new_notes: str = No
     test_id_col = find_column_letter_at_row(ws, "Test ID", header_rows[0])
     status_col  = find_column_letter_at_row(ws, "Status", header_rows[0])
     notes_col   = find_column_letter_at_row(ws, "Notes", header_rows[0])
+    freq_col    = find_column_letter_at_row(ws, "Frequency", header_rows[0])
 
     for row in range(header_rows[0] + 1, ws.max_row + 1):
         cell_value = str(ws[f"{test_id_col}{row}"].value).strip()
@@ -174,13 +180,60 @@ def update_test_row_by_id(ws, test_id: str, new_status: str, new_notes: str = No
             ws[f"{status_col}{row}"].value = new_status
             if new_notes is not None:
                 ws[f"{notes_col}{row}"].value = new_notes
+            if log_link is not None:
+                ws[f"{test_id_col}{row}"].hyperlink = log_link
+                ws[f"{test_id_col}{row}"].style = "Hyperlink"
+            if frequency is not None:
+                ws[f"{freq_col}{row}"].value = frequency
             return
 
     raise ValueError(f"Test ID '{test_id}' not found in sheet.")
 
-def safe_update_excel(path, test_id, new_status, new_notes=None):
+def update_metadata(ws, fields):
+    """
+    Set the value cell of the metadata rows at the top of the sheet, ex:
+    {"Firmware Version": "1.2.3"} fills the cell next to "Firmware Version:".
+    """
+    wanted = {label.strip().rstrip(":").lower(): value for label, value in fields.items()}
+    for row in range(1, ws.max_row + 1):
+        label = ws.cell(row=row, column=1).value
+        if label is None or str(label).strip() == "":
+            # The metadata block ends at the first blank row
+            break
+        key = str(label).strip().rstrip(":").lower()
+        if key in wanted:
+            ws.cell(row=row, column=2).value = wanted.pop(key)
+
+    if wanted:
+        missing = ", ".join(wanted)
+        raise ValueError(f"Metadata row(s) not found in sheet: {missing}")
+
+def safe_update_metadata(path, fields):
+    path = Path(path).resolve()
+
+    try:
+        force_close_excel_file(path)
+    except Exception as e:
+        print(f"⚠️ Could not close Excel file: {e}")
+
+    wb = load_workbook(path)
+    ws = wb.active
+    update_metadata(ws, fields)
+    wb.save(path)
+
+    try:
+        reopen_excel_file(path)
+    except Exception as e:
+        print(f"⚠️ Could not reopen Excel file: {e}")
+
+def safe_update_excel(path, test_id, new_status, new_notes=None, log_path=None, frequency=None):
     path = Path(path).resolve()
 
+    # Link relative to the report so the link survives moving both together
+    log_link = None
+    if log_path:
+        log_link = os.path.relpath(Path(log_path).resolve(), path.parent)
+
     try:
         force_close_excel_file(path)
     except Exception as e:
@@ -188,7 +241,7 @@ def safe_update_excel(path, test_id, new_status, new_notes=None):
 
     wb = load_workbook(path)
     ws = wb.active
-    update_test_row_by_id(ws, test_id, new_status, new_notes)
+    update_test_row_by_id(ws, test_id, new_status, new_notes, log_link, frequency)
     wb.save(path)
 
     try:
@@ -200,7 +253,8 @@ if __name__ == "__main__":
     if len(sys.argv) < 3:
         print("Usage:")
         print("  Format: python excel_format.py format <path_to_excel_file>")
-        print("  Update: python excel_format.py update <path_to_excel_file> <test_id> <new_status> [notes]")
+        print("  Update: python excel_format.py update <path_to_excel_file> <test_id> <new_status> [notes] [log_path] [frequency]")
+        print("  Metadata: python excel_format.py metadata <path_to_excel_file> <label> <value> [<label> <value> ...]")
         sys.exit(1)
 
     mode = sys.argv[1].lower()
@@ -211,13 +265,24 @@ if __name__ == "__main__":
 
     elif mode == "update":
         if len(sys.argv) < 5:
-            print("Usage: python excel_format.py update <path_to_excel_file> <test_id> <new_status> [notes]")
+            print("Usage: python excel_format.py update <path_to_excel_file> <test_id> <new_status> [notes] [log_path] [frequency]")
             sys.exit(1)
         test_id = sys.argv[3]
         new_status = sys.argv[4]
-        new_notes = sys.argv[5] if len(sys.argv) >= 6 else None
-        safe_update_excel(path, test_id, new_status, new_notes)
+        # An empty note, log path or frequency keeps the current cell
+        new_notes = sys.argv[5] if len(sys.argv) >= 6 and sys.argv[5] else None
+        log_path = sys.argv[6] if len(sys.argv) >= 7 and sys.argv[6] else None
+        frequency = sys.argv[7] if len(sys.argv) >= 8 and sys.argv[7] else None
+        safe_update_excel(path, test_id, new_status, new_notes, log_path, frequency)
+
+    elif mode == "metadata":
+        pairs = sys.argv[3:]
+        if not pairs or len(pairs) % 2 != 0:
+            print("Usage: python excel_format.py metadata <path_to_excel_file> <label> <value> [<label> <value> ...]")
+            sys.exit(1)
+        fields = dict(zip(pairs[0::2], pairs[1::2]))
+        safe_update_metadata(path, fields)
 
     else:
-        print(f"Unknown mode '{mode}'. Use 'format' or 'update'.")
+        print(f"Unknown mode '{mode}'. Use 'format', 'update' or 'metadata'.")
         sys.exit(1)
\ No newline at end of file
@@ -1,4 +1,4 @@
-# VERSION 1.1.6
+# VERSION 1.1.9
 
 import sys
 from pathlib import Path
@@ -7,6 +7,7 @@ from openpyxl import load_workbook
 from email.mime.multipart import MIMEMultipart
 from email.mime.text import MIMEText
 from email.mime.image import MIMEImage
+from email.mime.application import MIMEApplication
 from datetime import date
 
 # =============================================================================
@@ -17,12 +18,15 @@ EMAIL_TO_DEFAULT      = ["alice@example.com", "bob@example.com"]
 EXCEL_FILE_DEFAULT    = "validation_test_report.xlsx"
 IMAGES_FOLDER_DEFAULT = "images_github_issues/"
 EMAIL_ATTACHMENTS_FLDR    = "email_attachments/"
+IMAGE_SUFFIXES            = {".png", ".jpg", ".jpeg", ".gif", ".bmp"}
 
 STATUS_COLORS = {
     "Pass":    "#c6efce",
     "Fail":    "#ffc7ce",
     "Pending": "#ffeb9c",
     "Blocked": "#add8e6",
+    "Not verified": "#e7e6e6",
+    "Skipped": "#d9d9d9",
 }
 
 EMAIL_FILE_NAME = "validation_report_message"
@@ -231,12 +235,16 @@ class EmailBodyBuilder:
 
     def email_body_attach_misc_files(self, folder):
         """
-        Attach images from a folder as file attachments (not inline).
+        Attach the files of a folder, ex: images, config dumps and test logs,
+        as file attachments (not inline).
         """
-        for img_path in Path(folder).glob("*.*"):
-            img = MIMEImage(img_path.read_bytes(), _subtype=img_path.suffix.lstrip("."))
-            img.add_header("Content-Disposition", "attachment", filename=img_path.name)
-            self.msg.attach(img)
+        for file_path in Path(folder).glob("*.*"):
+            if file_path.suffix.lower() in IMAGE_SUFFIXES:
+                part = MIMEImage(file_path.read_bytes(), _subtype=file_path.suffix.lstrip("."))
+            else:
+                part = MIMEApplication(file_path.read_bytes())
+            part.add_header("Content-Disposition", "attachment", filename=file_path.name)
+            self.msg.attach(part)
 
     def email_body_add_outro(self, text):
         self._html_parts.append(f"<p>{text}</p>")
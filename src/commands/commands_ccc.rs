@@ -1,16 +1,42 @@
-use std::{error::Error, fs, io, path::Path, process::Command};
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
 use chrono::Local;
 
-use crate::supporters::misc::{get_key_entry_y, press_enter_no_message};
+use crate::supporters::misc::{get_key_entry_y, parse_duration, press_enter_no_message};
+use crate::supporters::process_ops::{
+    NO_TIMEOUT, output_with_timeout, run_with_retries, split_run_options,
+};
+use crate::supporters::sensor_config::{
+    ConfigValue, ConfigValues, parse_config_output, set_assignment,
+};
 
 const PATH_CCC_EXE: &str = "./ccc.exe";
+pub const CONFIG_DUMP_PREFIX: &str = "sensor_config_dump_";
+const DEFAULT_FACTORY_SKU: &str = "0";
+/// Image flashed by factory_init and flash_firmware unless told otherwise.
+pub const DEFAULT_FIRMWARE_IMAGE: &str = "ultra.cepbin";
+pub const WAIT_SENSOR_UP_KEYWORD: &str = "wait_until_sensor_up";
+const WAIT_SENSOR_UP_TIMEOUT: Duration = Duration::from_secs(60);
+const WAIT_SENSOR_UP_POLL: Duration = Duration::from_secs(1);
+// A probe of a sensor that is still booting may hang, it is killed after this
+const SENSOR_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+// Reads of the configuration are killed after this, they do not change it
+const CCC_QUERY_TIMEOUT: Duration = Duration::from_secs(120);
+// Shortest probe of `wait_until_sensor_up`
+const MIN_PROBE_TIMEOUT: Duration = Duration::from_millis(100);
 
-/// Trims, replaces "ccc" with the actual exe path, runs the command,
-/// and returns Ok(()) if the process exits successfully or Err on failure.
+/// Trims, replaces "ccc" with the actual exe path, runs the command with its
+/// `@timeout=`/`@retries=` options, and returns Ok(()) if the process exits
+/// successfully or Err on failure or timeout.
 fn ccc_command_runner(line: &str) -> Result<(), Box<dyn Error>> {
     // 1. Prepare command string
-    let trimmed = line.trim();
+    let (trimmed, options) = split_run_options(line)?;
     let command_line = trimmed.replacen("ccc", PATH_CCC_EXE, 1);
     let mut parts = command_line.split_whitespace();
     let exe = parts.next().unwrap_or(PATH_CCC_EXE);
@@ -25,21 +51,8 @@ fn ccc_command_runner(line: &str) -> Result<(), Box<dyn Error>> {
         )));
     }
 
-    // 2. Spawn process, convert any I/O error into Box<dyn Error>
-    let status = Command::new(exe)
-        .args(&args)
-        .status()
-        .map_err(|e| Box::new(e) as Box<dyn Error>)?;
-
-    // 3. Check exit code and wrap failure in Box<dyn Error>
-    if status.success() {
-        Ok(())
-    } else {
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("ccc command error: {}", status),
-        )))
-    }
+    // 3. Spawn process, kill it on timeout and retry if asked to
+    run_with_retries("ccc", exe_path, &args, &options)
 }
 
 fn diag_command_check_and_run(trimmed_line: &str) -> Result<bool, Box<dyn Error>> {
@@ -67,46 +80,189 @@ pub fn ccc_handler(trimmed_line: &str, auto: bool) -> Result<(), Box<dyn Error>>
     Ok(())
 }
 
-fn get_ccc_output(args: &str) -> Result<String, Box<dyn Error>> {
-    let output = Command::new(PATH_CCC_EXE)
-        .arg("get")
-        .args(args.split_whitespace())
-        .output()
-        .map_err(|e| Box::new(e) as Box<dyn Error>)?;
-
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        Ok(stdout.to_string())
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("ccc get command failed: {}", stderr),
-        )))
+fn get_ccc_output(subcommand: &str, args: &str) -> Result<String, Box<dyn Error>> {
+    get_ccc_output_within(subcommand, args, CCC_QUERY_TIMEOUT)
+}
+
+/// Output of `ccc <subcommand> <args>`, killing ccc if it runs longer than
+/// `timeout`.
+fn get_ccc_output_within(
+    subcommand: &str,
+    args: &str,
+    timeout: Duration,
+) -> Result<String, Box<dyn Error>> {
+    let args: Vec<&str> = [subcommand]
+        .into_iter()
+        .chain(args.split_whitespace())
+        .collect();
+    output_with_timeout(Path::new(PATH_CCC_EXE), &args, Some(timeout))
+        .map_err(|e| format!("ccc {} command failed: {}", subcommand, e).into())
+}
+
+/// Fields read with `ccc get <fields...>`.
+pub fn ccc_get_values(args: &str) -> Result<ConfigValues, Box<dyn Error>> {
+    let output = get_ccc_output("get", args)?;
+    println!("Output from ccc get {}: `{}`", args, output.trim());
+    let fields: Vec<&str> = args
+        .split_whitespace()
+        .filter(|a| !a.starts_with('-'))
+        .collect();
+    parse_config_output(&output, &fields)
+}
+
+/// Value of the first field of `ccc get <fields...>`.
+pub fn ccc_get_value(args: &str) -> Result<ConfigValue, Box<dyn Error>> {
+    let field = args
+        .split_whitespace()
+        .find(|a| !a.starts_with('-'))
+        .ok_or("Missing the field name of ccc get")?;
+    let mut values = ccc_get_values(args)?;
+    match values.shift_remove(field) {
+        Some(value) => Ok(value),
+        None => Err(format!("Field '{}' not found in the ccc get output", field).into()),
     }
 }
 
-fn get_ccc_output_integer(args: &str) -> Result<i32, Box<dyn Error>> {
-    let output = get_ccc_output(args)?;
-    println!("Output from ccc get {}: `{}`", args, output);
-    output
-        .trim()
-        .split(|c| c == '[' || c == ']')
-        .nth(1)
-        .ok_or("Failed to extract integer from output string")?
-        .trim()
-        .parse::<i32>()
-        .map_err(|e| Box::new(e) as Box<dyn Error>)
+/// Every field of the sensor configuration, read with `ccc get-all`.
+pub fn ccc_get_all() -> Result<ConfigValues, Box<dyn Error>> {
+    let output = get_ccc_output("get-all", "")?;
+    parse_config_output(&output, &[])
+}
+
+/// Write `fields` with a single `ccc set name=value,... --force`.
+pub fn ccc_set(fields: &[(&str, &ConfigValue)]) -> Result<(), Box<dyn Error>> {
+    let assignments = fields
+        .iter()
+        .map(|(name, value)| set_assignment(name, value))
+        .collect::<Result<Vec<_>, _>>()?;
+    ccc_command_runner(&format!("ccc set {} --force", assignments.join(",")))
 }
 
-pub fn factory_init() -> Result<(), Box<dyn Error>> {
+/// Save the `ccc get-all` output to `path`, and return its fields.
+pub fn save_config_snapshot(path: &Path) -> Result<ConfigValues, Box<dyn Error>> {
+    let output = get_ccc_output("get-all", "")?;
+    fs::write(path, &output)?;
+    parse_config_output(&output, &[])
+}
+
+/// `wait_until_sensor_up [timeout=60s] [poll=1s]`, polls
+/// `ccc get serial_number` every `poll` until the sensor answers, and fails
+/// the step after `timeout`.
+pub fn wait_until_sensor_up(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
+    let (timeout, poll) = wait_sensor_up_options(trimmed_line)?;
+    println!(
+        "  - Waiting up to {}s for the sensor to answer",
+        timeout.as_secs()
+    );
+
+    let start = Instant::now();
+    loop {
+        let probe_timeout = timeout
+            .saturating_sub(start.elapsed())
+            .clamp(MIN_PROBE_TIMEOUT, SENSOR_PROBE_TIMEOUT);
+        let last_error = match get_ccc_output_within("get", "serial_number", probe_timeout)
+            .and_then(|output| parse_config_output(&output, &["serial_number"]))
+        {
+            Ok(values) => match values.get("serial_number") {
+                Some(serial_number) => {
+                    println!(
+                        "  - Sensor {} answered after {}s",
+                        serial_number,
+                        start.elapsed().as_secs()
+                    );
+                    return Ok(());
+                }
+                None => "no serial_number in the ccc get output".to_string(),
+            },
+            Err(e) => e.to_string(),
+        };
+        if start.elapsed() >= timeout {
+            return Err(format!(
+                "Sensor did not answer 'ccc get serial_number' within {}s, last error: {}",
+                timeout.as_secs(),
+                last_error.trim()
+            )
+            .into());
+        }
+        sleep(poll);
+    }
+}
+
+/// The `timeout=` and `poll=` options of `wait_until_sensor_up`.
+pub fn wait_sensor_up_options(trimmed_line: &str) -> Result<(Duration, Duration), Box<dyn Error>> {
+    let mut timeout = WAIT_SENSOR_UP_TIMEOUT;
+    let mut poll = WAIT_SENSOR_UP_POLL;
+    for arg in trimmed_line.split_whitespace().skip(1) {
+        match arg.split_once('=') {
+            Some(("timeout", value)) => timeout = parse_duration(value)?,
+            Some(("poll", value)) => poll = parse_duration(value)?,
+            _ => {
+                return Err(format!(
+                    "Unknown argument '{}', usage: {} [timeout=60s] [poll=1s]",
+                    arg, WAIT_SENSOR_UP_KEYWORD
+                )
+                .into());
+            }
+        }
+    }
+    Ok((timeout, poll))
+}
+
+/// Options of `factory_init [sku=N] [image=FILE] [serial_number=N]`. The
+/// serial number defaults to the one read from the sensor.
+struct FactoryInitOptions {
+    sku: String,
+    image: String,
+    serial_number: Option<String>,
+}
+
+impl FactoryInitOptions {
+    fn parse(trimmed_line: &str) -> Result<Self, Box<dyn Error>> {
+        let mut options = FactoryInitOptions {
+            sku: DEFAULT_FACTORY_SKU.to_string(),
+            image: DEFAULT_FIRMWARE_IMAGE.to_string(),
+            serial_number: None,
+        };
+        for arg in trimmed_line.split_whitespace().skip(1) {
+            let (key, value) = arg.split_once('=').ok_or_else(|| {
+                format!(
+                    "Invalid factory_init argument '{}', expected KEY=VALUE",
+                    arg
+                )
+            })?;
+            match key {
+                "sku" => options.sku = value.to_string(),
+                "image" => options.image = value.to_string(),
+                "serial_number" => options.serial_number = Some(value.to_string()),
+                _ => {
+                    return Err(format!(
+                        "Unknown factory_init argument '{}', expected sku, image or serial_number",
+                        key
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+pub fn factory_init(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
     println!("Running factory_init...");
+    let options = FactoryInitOptions::parse(trimmed_line)?;
 
-    let serial_number = get_ccc_output_integer("serial_number")?.to_string();
+    let serial_number = match options.serial_number {
+        Some(serial_number) => serial_number,
+        None => ccc_get_value("serial_number")?
+            .as_i64()
+            .ok_or("Failed to extract integer serial_number from the ccc get output")?
+            .to_string(),
+    };
 
+    // Flashing the image takes minutes, it must not be killed midway
     let line = format!(
-        "ccc factory-init --sku 0 --serial-number {} ultra.cepbin",
-        serial_number
+        "ccc factory-init --sku {} --serial-number {} {} @timeout={}",
+        options.sku, serial_number, options.image, NO_TIMEOUT
     );
 
     println!("Running {}", line);
@@ -114,30 +270,22 @@ pub fn factory_init() -> Result<(), Box<dyn Error>> {
     ccc_command_runner(&line)
 }
 
-pub fn get_config_dump(path: &str) -> Result<(), Box<dyn Error>> {
-    if !std::path::Path::new(path).exists() {
-        std::fs::create_dir_all(path)?;
-    }
-
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        if file_type.is_file()
-            && entry
-                .file_name()
-                .to_string_lossy()
-                .starts_with("sensor_config_dump_")
-        {
-            fs::remove_file(entry.path())?;
-        }
-    }
+/// Save a `get-all` dump of the sensor configuration in `dir` and return its
+/// path. Earlier dumps are kept.
+pub fn get_config_dump(dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
 
     let now = Local::now();
     let timestamp = now.format("[%d_%m_%Y]_[%Hhr_%Mmin]").to_string();
+    let dump_path = dir.join(format!("{}{}.txt", CONFIG_DUMP_PREFIX, timestamp));
 
     // Do a get-all to get config at email generation time
     ccc_handler(
-        &format!("ccc get-all -o {path}/sensor_config_dump_{timestamp}.txt -d ultra_config.toml"),
+        &format!(
+            "ccc get-all -o {} -d ultra_config.toml",
+            dump_path.display()
+        ),
         true,
-    )
+    )?;
+    Ok(dump_path)
 }
@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::commands::commands_ccc::{ccc_get_all, ccc_set};
+use crate::files_ops::campaign_ops::TestArtifacts;
+use crate::files_ops::profile_ops::{Profile, profile_path};
+use crate::supporters::misc::press_enter_no_message;
+use crate::supporters::sensor_config::{
+    ConfigChange, ConfigValues, diff_config, parse_config_output, parse_config_toml,
+};
+use crate::test_ops::test_ops_config_snapshots::SnapshotConfig;
+
+pub const RESTORE_BASELINE_KEYWORD: &str = "restore_baseline";
+pub const APPLY_PROFILE_KEYWORD: &str = "apply_profile";
+const BATCH_OPTION: &str = "batch=";
+const DEFAULT_BATCH_SIZE: usize = 8;
+// Fields set last, the sensor may stop answering once its address changes
+const NETWORK_FIELD_SUFFIXES: &[&str] = &["_ip", "_mac", "_port", "_mask", "_gateway"];
+
+fn is_network_field(field: &str) -> bool {
+    NETWORK_FIELD_SUFFIXES.iter().any(|s| field.ends_with(s))
+}
+
+/// Fields of a saved `get-all` dump, or of a TOML file of `name = value`
+/// fields.
+fn read_config_file(path: &Path) -> Result<ConfigValues, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+    if path.extension().is_some_and(|e| e == "toml") {
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|e| format!("Invalid config file '{}': {}", path.display(), e))?;
+        parse_config_toml(&table)
+    } else {
+        parse_config_output(&content, &[])
+    }
+}
+
+/// Fields of `target` the sensor does not have yet. Fields only on the
+/// sensor, and fields ignored by the snapshots, ex: an uptime, are left out.
+fn pending_changes(
+    current: &ConfigValues,
+    target: &ConfigValues,
+    ignored: &[String],
+) -> Vec<ConfigChange> {
+    diff_config(current, target)
+        .into_iter()
+        .filter(|c| c.after.is_some() && !ignored.contains(&c.field))
+        .collect()
+}
+
+fn set_batch(batch: &[ConfigChange]) {
+    let fields: Vec<_> = batch
+        .iter()
+        .filter_map(|c| c.after.as_ref().map(|v| (c.field.as_str(), v)))
+        .collect();
+    if let Err(e) = ccc_set(&fields) {
+        if fields.len() == 1 {
+            print_warn_ln!("Failed to set '{}': {}", fields[0].0, e);
+            return;
+        }
+        // Find the failing fields by setting them one by one
+        print_warn_ln!("Batch of {} field(s) failed: {}", fields.len(), e);
+        for field in fields {
+            if let Err(e) = ccc_set(&[field]) {
+                print_warn_ln!("Failed to set '{}': {}", field.0, e);
+            }
+        }
+    }
+}
+
+/// Set the fields of `target` that differ on the sensor with `ccc set`, at
+/// most `batch_size` fields per command or all of them in one command, then
+/// read the configuration back. Fails with the fields that still differ.
+pub fn restore_config(
+    target: &ConfigValues,
+    batch_size: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let ignored = SnapshotConfig::load()?.ignore_fields;
+    let changes = pending_changes(&ccc_get_all()?, target, &ignored);
+    if changes.is_empty() {
+        println!("  - Sensor configuration already matches, nothing to restore");
+        return Ok(());
+    }
+
+    let (network, other): (Vec<ConfigChange>, Vec<ConfigChange>) = changes
+        .iter()
+        .cloned()
+        .partition(|c| is_network_field(&c.field));
+    match batch_size {
+        Some(batch_size) => {
+            println!(
+                "  - Restoring {} field(s) in batches of up to {}",
+                changes.len(),
+                batch_size
+            );
+            for batch in other.chunks(batch_size).chain(network.chunks(batch_size)) {
+                set_batch(batch);
+            }
+        }
+        None => {
+            println!("  - Restoring {} field(s) in one ccc set", changes.len());
+            let ordered: Vec<ConfigChange> = other.into_iter().chain(network).collect();
+            set_batch(&ordered);
+        }
+    }
+
+    let remaining = pending_changes(&ccc_get_all()?, target, &ignored);
+    if remaining.is_empty() {
+        println!("  - {} field(s) restored and read back", changes.len());
+        return Ok(());
+    }
+    for change in &remaining {
+        println!("  - Not restored: {}", change);
+    }
+    let fields: Vec<&str> = remaining.iter().map(|c| c.field.as_str()).collect();
+    Err(format!(
+        "{} of {} field(s) could not be restored: {}",
+        remaining.len(),
+        changes.len(),
+        fields.join(", ")
+    )
+    .into())
+}
+
+/// The argument of a `restore_baseline` or `apply_profile` line, and its
+/// `batch=` size if given.
+type BatchArgs<'a> = (Option<&'a str>, Option<usize>);
+
+/// Split the `batch=` option out of the arguments of `keyword`, allowing a
+/// single other argument.
+fn batch_args<'a>(
+    trimmed_line: &'a str,
+    keyword: &str,
+    usage: &str,
+) -> Result<BatchArgs<'a>, Box<dyn Error>> {
+    let mut target = None;
+    let mut batch_size = None;
+    for arg in trimmed_line.split_whitespace().skip(1) {
+        if let Some(value) = arg.strip_prefix(BATCH_OPTION) {
+            batch_size = Some(
+                value
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| format!("Invalid batch size '{}'", value))?,
+            );
+        } else if target.is_none() {
+            target = Some(arg);
+        } else {
+            return Err(format!(
+                "Unexpected argument '{}', usage: {} {}",
+                arg, keyword, usage
+            )
+            .into());
+        }
+    }
+    Ok((target, batch_size))
+}
+
+/// `restore_baseline [FILE|PROFILE] [batch=N]`, applies a saved `get-all`
+/// dump, a TOML file of fields or a named profile back to the sensor. Without
+/// argument, the campaign baseline taken before its first test is applied.
+pub fn restore_baseline(
+    artifacts: &TestArtifacts,
+    trimmed_line: &str,
+    auto: bool,
+) -> Result<(), Box<dyn Error>> {
+    let (target, batch_size) = batch_args(
+        trimmed_line,
+        RESTORE_BASELINE_KEYWORD,
+        "[FILE|PROFILE] [batch=N]",
+    )?;
+
+    let (source, target) = match target {
+        Some(file) if Path::new(file).exists() => {
+            (file.to_string(), read_config_file(Path::new(file))?)
+        }
+        Some(name) if profile_path(name).is_ok_and(|p| p.exists()) => {
+            (format!("profile '{}'", name), Profile::load(name)?.fields)
+        }
+        Some(file) => {
+            return Err(format!("No dump file or profile named '{}'", file).into());
+        }
+        None => {
+            let baseline = artifacts.baseline_config_path();
+            if !baseline.exists() {
+                return Err(format!(
+                    "No campaign baseline at '{}' yet, give the dump to restore",
+                    baseline.display()
+                )
+                .into());
+            }
+            (baseline.display().to_string(), read_config_file(&baseline)?)
+        }
+    };
+    if !auto {
+        println!("  - Press Enter to RESTORE: {}", source);
+        press_enter_no_message();
+    }
+    println!("  - Restoring the sensor configuration from {}", source);
+    restore_config(&target, Some(batch_size.unwrap_or(DEFAULT_BATCH_SIZE)))
+}
+
+/// `apply_profile <name> [batch=N]`, sets the fields of `profiles/<name>.toml`
+/// with a single `ccc set`, or `N` fields per command, and reads them back.
+/// Fields the sensor does not report fail the step before anything is set.
+pub fn apply_profile(trimmed_line: &str, auto: bool) -> Result<(), Box<dyn Error>> {
+    let usage = "<name> [batch=N]";
+    let (name, batch_size) = batch_args(trimmed_line, APPLY_PROFILE_KEYWORD, usage)?;
+    let name = name.ok_or_else(|| format!("Usage: {} {}", APPLY_PROFILE_KEYWORD, usage))?;
+    let profile = Profile::load(name)?;
+
+    let current = ccc_get_all()?;
+    let unknown: Vec<&str> = profile
+        .fields
+        .keys()
+        .filter(|field| !current.contains_key(*field))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "Profile '{}' sets fields the sensor does not have: {}",
+            profile.name,
+            unknown.join(", ")
+        )
+        .into());
+    }
+
+    if !auto {
+        println!("  - Press Enter to APPLY profile: {}", profile.name);
+        press_enter_no_message();
+    }
+    println!(
+        "  - Applying profile '{}': {} field(s)",
+        profile.name,
+        profile.fields.len()
+    );
+    restore_config(&profile.fields, batch_size)
+}
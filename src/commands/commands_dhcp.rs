@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::time::Duration;
+
+use crate::files_ops::campaign_ops::TestArtifacts;
+use crate::supporters::dhcp_server::{DhcpConfig, DhcpServerHandle, format_mac, parse_mac};
+use crate::supporters::misc::{human_readable_time, parse_duration};
+
+const DEFAULT_LEASE_WAIT_S: u64 = 30;
+const DHCP_EVENT_LOG_SUFFIX: &str = "_dhcp_events.csv";
+
+/// Handles `dhcp_server [start|stop|status] [KEY=VALUE...]`.
+/// A bare `dhcp_server` or one followed only by overrides starts the server.
+fn dhcp_server_command(
+    artifacts: &TestArtifacts,
+    args: &[&str],
+    server: &mut Option<DhcpServerHandle>,
+) -> Result<(), Box<dyn Error>> {
+    let (action, overrides) = match args.first() {
+        Some(&"start") | Some(&"stop") | Some(&"status") => (args[0], &args[1..]),
+        _ => ("start", args),
+    };
+
+    match action {
+        "start" => {
+            if server.as_ref().is_some_and(|s| s.is_running()) {
+                return Err("DHCP server is already running, stop it first".into());
+            }
+            let mut config = DhcpConfig::load()?;
+            config.apply_args(overrides)?;
+            let event_log = match artifacts.path(DHCP_EVENT_LOG_SUFFIX) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    print_warn_ln!("DHCP event log disabled: {}", e);
+                    None
+                }
+            };
+            *server = Some(DhcpServerHandle::start(config, event_log)?);
+        }
+        "stop" => match server.take() {
+            Some(mut s) => s.stop(),
+            None => {
+                print_warn_ln!("DHCP server is not running.");
+            }
+        },
+        _ => dhcp_print_status(server.as_ref()),
+    }
+    Ok(())
+}
+
+fn dhcp_print_status(server: Option<&DhcpServerHandle>) {
+    let Some(server) = server.filter(|s| s.is_running()) else {
+        println!("[DHCP] Server is not running.");
+        return;
+    };
+
+    let config = server.config();
+    let pool_end = u32::from(config.pool_start) + config.pool_size - 1;
+    println!(
+        "[DHCP] Server running on '{}' ({}), pool {} - {}",
+        config.nic_name,
+        config.server_ip,
+        config.pool_start,
+        std::net::Ipv4Addr::from(pool_end)
+    );
+
+    let leases = server.leases();
+    if leases.is_empty() {
+        println!("[DHCP] No active leases.");
+        return;
+    }
+    println!("    {:<19}{:<17}Expires in", "MAC", "IP");
+    for lease in leases {
+        println!(
+            "    {:<19}{:<17}{}",
+            format_mac(&lease.mac),
+            lease.ip,
+            human_readable_time(lease.expires_in.as_secs() as u32)
+        );
+    }
+}
+
+/// Handles `dhcp_expect_lease [mac=<MAC>] [within <DURATION>]`.
+fn dhcp_expect_lease(
+    args: &[&str],
+    server: &Option<DhcpServerHandle>,
+) -> Result<(), Box<dyn Error>> {
+    let server = server
+        .as_ref()
+        .ok_or("dhcp_expect_lease requires a running DHCP server")?;
+
+    let mut mac = None;
+    let mut timeout = Duration::from_secs(DEFAULT_LEASE_WAIT_S);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(value) = arg.strip_prefix("mac=") {
+            mac = Some(parse_mac(value)?);
+        } else if *arg == "within" {
+            let value = iter.next().ok_or("Missing duration after 'within'")?;
+            timeout = parse_duration(value)?;
+        } else if let Some(value) = arg.strip_prefix("within=") {
+            timeout = parse_duration(value)?;
+        } else {
+            return Err(format!("Unknown dhcp_expect_lease argument '{}'", arg).into());
+        }
+    }
+
+    println!(
+        "[DHCP] Waiting up to {} for a lease{}",
+        human_readable_time(timeout.as_secs() as u32),
+        mac.map(|m| format!(" to {}", format_mac(&m)))
+            .unwrap_or_default()
+    );
+    let lease = server.wait_for_lease(mac, timeout)?;
+    println!(
+        "[DHCP] Lease found: {} -> {}",
+        format_mac(&lease.mac),
+        lease.ip
+    );
+    Ok(())
+}
+
+pub fn dhcp_handler(
+    artifacts: &TestArtifacts,
+    trimmed_line: &str,
+    server: &mut Option<DhcpServerHandle>,
+) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = trimmed_line.split_whitespace().collect();
+
+    match args[0] {
+        "dhcp_server" => dhcp_server_command(artifacts, &args[1..], server),
+        "dhcp_expect_lease" => dhcp_expect_lease(&args[1..], server),
+        other => Err(format!("Unknown DHCP instruction '{}'", other).into()),
+    }
+}
@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::time::Duration;
+
+use crate::commands::commands_ccc::{
+    DEFAULT_FIRMWARE_IMAGE, WAIT_SENSOR_UP_KEYWORD, ccc_get_value, ccc_handler,
+    wait_until_sensor_up,
+};
+use crate::files_ops::campaign_ops::TestArtifacts;
+use crate::files_ops::firmware_ops::FirmwareImage;
+use crate::supporters::log_ops::log_line;
+use crate::supporters::misc::{parse_duration, press_enter_no_message};
+use crate::supporters::process_ops::NO_TIMEOUT;
+
+pub const FLASH_FIRMWARE_KEYWORD: &str = "flash_firmware";
+const FLASH_USAGE: &str = "[FILE] [version=X] [timeout=DURATION] [boot_timeout=120s]";
+const FIRMWARE_STREAM: &str = "firmware";
+// Field of `ccc get` holding the version the sensor runs
+const FIRMWARE_VERSION_FIELD: &str = "firmware_version";
+// Usual update time, for the listing. The update is only killed with timeout=
+const FLASH_ESTIMATE: Duration = Duration::from_secs(600);
+const BOOT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Options of `flash_firmware [FILE] [version=X] [timeout=DURATION] [boot_timeout=120s]`.
+/// The image defaults to the one pinned for the campaign.
+struct FlashOptions {
+    file: Option<String>,
+    version: Option<String>,
+    timeout: Option<Duration>,
+    boot_timeout: Duration,
+}
+
+impl FlashOptions {
+    fn parse(trimmed_line: &str) -> Result<Self, Box<dyn Error>> {
+        let mut options = FlashOptions {
+            file: None,
+            version: None,
+            timeout: None,
+            boot_timeout: BOOT_TIMEOUT,
+        };
+        for arg in trimmed_line.split_whitespace().skip(1) {
+            match arg.split_once('=') {
+                Some(("version", value)) => options.version = Some(value.to_string()),
+                Some(("timeout", value)) => options.timeout = Some(parse_duration(value)?),
+                Some(("boot_timeout", value)) => options.boot_timeout = parse_duration(value)?,
+                None if options.file.is_none() => options.file = Some(arg.to_string()),
+                _ => {
+                    return Err(format!(
+                        "Unexpected argument '{}', usage: {} {}",
+                        arg, FLASH_FIRMWARE_KEYWORD, FLASH_USAGE
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Expected time of `flash_firmware`: the update, then the boot.
+pub fn flash_firmware_duration(trimmed_line: &str) -> Duration {
+    FlashOptions::parse(trimmed_line)
+        .map(|o| o.timeout.unwrap_or(FLASH_ESTIMATE) + o.boot_timeout)
+        .unwrap_or_default()
+}
+
+/// `flash_firmware [FILE] [version=X]`, flashes the image, by default the one
+/// pinned for the campaign or else `ultra.cepbin`, with
+/// `ccc update-firmware`, waits for the sensor to boot and reads the version
+/// it reports, failing the step if it is not `version`. The image, its hash
+/// and the version are recorded in the report metadata.
+pub fn flash_firmware(
+    artifacts: &TestArtifacts,
+    trimmed_line: &str,
+    auto: bool,
+) -> Result<(), Box<dyn Error>> {
+    let options = FlashOptions::parse(trimmed_line)?;
+    let image = match (&options.file, &artifacts.firmware) {
+        (Some(file), pinned) => {
+            let image = FirmwareImage::from_file(file)?;
+            if let Some(pinned) = pinned
+                && pinned.sha256 != image.sha256
+            {
+                println!(
+                    "  - Flashing '{}' instead of the pinned '{}'",
+                    image.file, pinned.file
+                );
+            }
+            image
+        }
+        (None, Some(pinned)) => {
+            pinned.verify()?;
+            pinned.clone()
+        }
+        (None, None) => {
+            println!(
+                "  - No firmware pinned for the campaign, flashing '{}'",
+                DEFAULT_FIRMWARE_IMAGE
+            );
+            FirmwareImage::from_file(DEFAULT_FIRMWARE_IMAGE).map_err(|e| {
+                format!(
+                    "{}, give the image to {} or run vtg test --firmware <file>",
+                    e, FLASH_FIRMWARE_KEYWORD
+                )
+            })?
+        }
+    };
+
+    println!("  - Firmware '{}', SHA-256 {}", image.file, image.sha256);
+    if !auto {
+        println!("  - Press Enter to FLASH: {}", image.file);
+        press_enter_no_message();
+    }
+    log_line(
+        FIRMWARE_STREAM,
+        &format!("flashing {} sha256={}", image.file, image.sha256),
+    );
+    let timeout = match options.timeout {
+        Some(timeout) => format!("{}s", timeout.as_secs()),
+        None => NO_TIMEOUT.to_string(),
+    };
+    ccc_handler(
+        &format!(
+            "ccc update-firmware {} --force @timeout={}",
+            image.file, timeout
+        ),
+        true,
+    )?;
+    wait_until_sensor_up(&format!(
+        "{} timeout={}s",
+        WAIT_SENSOR_UP_KEYWORD,
+        options.boot_timeout.as_secs()
+    ))?;
+
+    let version = ccc_get_value(FIRMWARE_VERSION_FIELD)?.to_string();
+    log_line(FIRMWARE_STREAM, &format!("sensor reports {}", version));
+    if let Some(expected) = &options.version
+        && version != *expected
+    {
+        return Err(format!(
+            "Sensor reports firmware version '{}' after flashing '{}', expected '{}'",
+            version, image.file, expected
+        )
+        .into());
+    }
+    println!("  - Sensor reports firmware version {}", version);
+
+    if let Some(report) = &artifacts.report
+        && let Err(e) = image.record_in_report(report, Some(&version))
+    {
+        print_warn_ln!("Failed to record the firmware in the report: {}", e);
+    }
+    Ok(())
+}
@@ -1,6 +1,5 @@
 use std::error::Error;
 
-use crate::supporters::dhcp_server::dhcp_server_runner;
 use crate::supporters::misc::{press_enter, wait_s};
 
 pub fn generic_runner(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
@@ -12,8 +11,6 @@ pub fn generic_runner(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
         wait_s(timeout);
     } else if args[0] == "wait_e" {
         press_enter();
-    } else if args[0] == "dhcp_server" {
-        dhcp_server_runner()?;
     }
     Ok(())
 }
@@ -1,9 +1,11 @@
 use std::{error::Error, io, path::Path};
 
+use crate::supporters::process_ops::{run_with_retries, split_run_options};
+
 const PATH_PANORAMA_CLI_EXE: &str = "./panorama_cli.exe";
 
 fn panorama_cli_runner(line: &str) -> Result<(), Box<dyn Error>> {
-    let trimmed = line.trim();
+    let (trimmed, options) = split_run_options(line)?;
     let command_line = trimmed.replacen("panorama", &format!("{PATH_PANORAMA_CLI_EXE} run -t"), 1);
     let mut parts = command_line.split_whitespace();
     let exe = parts.next().unwrap_or(PATH_PANORAMA_CLI_EXE);
@@ -18,19 +20,7 @@ fn panorama_cli_runner(line: &str) -> Result<(), Box<dyn Error>> {
         )));
     }
 
-    let status = std::process::Command::new(exe)
-        .args(&args)
-        .status()
-        .map_err(|e| Box::new(e) as Box<dyn Error>)?;
-
-    if status.success() {
-        Ok(())
-    } else {
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("panorama_cli command error: {}", status),
-        )))
-    }
+    run_with_retries("panorama_cli", exe_path, &args, &options)
 }
 
 pub fn panorama_cli_handler(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
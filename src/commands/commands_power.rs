@@ -0,0 +1,60 @@
+use std::error::Error;
+
+use crate::supporters::log_ops::log_line;
+use crate::supporters::misc::{parse_duration, press_enter_no_message, wait_s};
+use crate::supporters::power_control::PowerConfig;
+
+pub const POWER_KEYWORD: &str = "power";
+const POWER_USAGE: &str = "Usage: power off|on|cycle <seconds>";
+
+/// Off time of `power cycle <seconds>`, ex: `power cycle 5` or `power cycle 5s`.
+pub fn power_cycle_duration(trimmed_line: &str) -> Option<u32> {
+    let mut args = trimmed_line.split_whitespace().skip(1);
+    if args.next() != Some("cycle") {
+        return None;
+    }
+    parse_duration(args.next()?)
+        .ok()
+        .map(|d| d.as_secs() as u32)
+}
+
+/// Handles `power off|on|cycle <seconds>` with the controller of
+/// `power_config.toml`. `in_block` is set in background and parallel blocks,
+/// where the technician cannot be asked to switch the power.
+pub fn power_handler(trimmed_line: &str, auto: bool, in_block: bool) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = trimmed_line.split_whitespace().skip(1).collect();
+    let action = *args.first().ok_or(POWER_USAGE)?;
+    let off_seconds = match (action, args.len()) {
+        ("on" | "off", 1) => None,
+        ("cycle", 2) => Some(
+            power_cycle_duration(trimmed_line)
+                .ok_or_else(|| format!("Invalid power cycle time '{}'", args[1]))?,
+        ),
+        _ => return Err(POWER_USAGE.into()),
+    };
+
+    let mut controller = PowerConfig::load()?.controller(!in_block)?;
+    if !auto {
+        println!("  - Press Enter to RUN: {}", trimmed_line);
+        press_enter_no_message();
+    }
+
+    let mut switch = |on: bool| -> Result<(), Box<dyn Error>> {
+        let state = if on { "on" } else { "off" };
+        println!("  - Power {}", state);
+        log_line(POWER_KEYWORD, &format!("power {}", state));
+        controller
+            .set_power(on)
+            .map_err(|e| format!("Failed to power the sensor {}: {}", state, e).into())
+    };
+    match (action, off_seconds) {
+        ("on", _) => switch(true),
+        ("off", _) => switch(false),
+        (_, Some(seconds)) => {
+            switch(false)?;
+            wait_s(seconds);
+            switch(true)
+        }
+        _ => Err(POWER_USAGE.into()),
+    }
+}
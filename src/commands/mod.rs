@@ -1,3 +1,7 @@
 pub mod commands_ccc;
+pub mod commands_config;
+pub mod commands_dhcp;
+pub mod commands_firmware;
 pub mod commands_generic;
 pub mod commands_panorama;
+pub mod commands_power;
@@ -0,0 +1,381 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use crate::files_ops::firmware_ops::FirmwareImage;
+use crate::test_ops::test_ops_run::TestOutcome;
+
+pub const CAMPAIGNS_DIR: &str = "campaigns";
+const MANIFEST_FILE: &str = "campaign.toml";
+const RUN_RECORD_FILE: &str = "run_record.csv";
+const PCAPS_DIR: &str = "pcaps";
+const LOGS_DIR: &str = "logs";
+const CONFIG_DUMPS_DIR: &str = "config_dumps";
+const BASELINE_CONFIG_FILE: &str = "baseline_config.txt";
+const CREATED_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Where the artifacts of one test attempt are written, ex:
+/// `<campaign>/pcaps/1.1_attempt2.pcap`, and where its sensor configuration
+/// snapshots go, ex: `<campaign>/config_dumps/1.1_attempt2_config_before.txt`.
+#[derive(Debug, Clone)]
+pub struct TestArtifacts {
+    pub dir: PathBuf,
+    pub config_dir: PathBuf,
+    pub name: String,
+    /// Firmware image pinned for the campaign, flashed by `flash_firmware`.
+    pub firmware: Option<FirmwareImage>,
+    /// Report whose metadata records the flashed firmware.
+    pub report: Option<PathBuf>,
+}
+
+impl TestArtifacts {
+    /// Path of the artifact `<name><suffix>`, creating the directory.
+    pub fn path(&self, suffix: &str) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        Ok(self.dir.join(format!("{}{}", self.name, suffix)))
+    }
+
+    /// Path of the configuration snapshot `<name>_config_<stage>.txt`,
+    /// creating the directory.
+    pub fn config_snapshot_path(&self, stage: &str) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(&self.config_dir)?;
+        Ok(self
+            .config_dir
+            .join(format!("{}_config_{}.txt", self.name, stage)))
+    }
+
+    /// Configuration the campaign started from, taken before its first test.
+    pub fn baseline_config_path(&self) -> PathBuf {
+        self.config_dir.join(BASELINE_CONFIG_FILE)
+    }
+
+    /// Artifacts written so far, ex: the pcap, the DHCP event log and the
+    /// configuration snapshots.
+    pub fn existing(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        // The separator avoids matching `1.1_attempt10` for `1.1_attempt1`
+        let prefixes = [format!("{}.", self.name), format!("{}_", self.name)];
+        let mut files = Vec::new();
+        for dir in [&self.dir, &self.config_dir] {
+            if !dir.exists() {
+                continue;
+            }
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if path.is_file() && prefixes.iter().any(|p| name.starts_with(p)) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// One attempt of a test in the run record.
+#[derive(Debug, Clone)]
+pub struct AttemptRecord {
+    pub timestamp: String,
+    pub attempt: u32,
+    pub outcome: String,
+    pub notes: String,
+    /// Paths relative to the campaign directory, separated by `; `.
+    pub artifacts: String,
+}
+
+/// Replace the characters that are not allowed in file names, ex: the `:`
+/// of `1:POINT_CLOUD`.
+fn file_name_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// A validation campaign: the directory holding the instruction file, the
+/// report and everything produced while running its tests, ex:
+/// `campaigns/my_list_20250101_120000/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Campaign {
+    #[serde(skip)]
+    pub dir: PathBuf,
+    pub name: String,
+    pub created: String,
+    pub instruction_file: String,
+    pub report_file: String,
+    /// Firmware image under test, set with `vtg test --firmware <file>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<FirmwareImage>,
+}
+
+impl Campaign {
+    /// Create `campaigns/<name>_<timestamp>/` and its manifest.
+    pub fn create(
+        name: &str,
+        instruction_file: &str,
+        report_file: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let now = Local::now();
+        let dir = Path::new(CAMPAIGNS_DIR).join(format!(
+            "{}_{}",
+            file_name_safe(name),
+            now.format("%Y%m%d_%H%M%S")
+        ));
+        if dir.exists() {
+            return Err(format!("Campaign directory already exists: {}", dir.display()).into());
+        }
+        fs::create_dir_all(&dir)?;
+
+        let campaign = Campaign {
+            dir,
+            name: name.to_string(),
+            created: now.format(CREATED_FORMAT).to_string(),
+            instruction_file: instruction_file.to_string(),
+            report_file: report_file.to_string(),
+            firmware: None,
+        };
+        campaign.save()?;
+        println!("Campaign directory: {}", campaign.dir.display());
+        Ok(campaign)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        fs::write(self.dir.join(MANIFEST_FILE), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Pin the firmware image under test for the whole campaign. Campaigns
+    /// without manifest only keep it for the current run.
+    pub fn pin_firmware(&mut self, file: &str) -> Result<FirmwareImage, Box<dyn Error>> {
+        let image = FirmwareImage::from_file(file)?;
+        if let Some(pinned) = &self.firmware
+            && pinned.sha256 != image.sha256
+        {
+            print_warn_ln!(
+                "Replacing the pinned firmware '{}' ({}) with '{}' ({})",
+                pinned.file,
+                pinned.sha256,
+                image.file,
+                image.sha256
+            );
+        }
+        self.firmware = Some(image.clone());
+        if self.dir.join(MANIFEST_FILE).exists() {
+            self.save()?;
+        }
+        Ok(image)
+    }
+
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, Box<dyn Error>> {
+        let manifest = dir.as_ref().join(MANIFEST_FILE);
+        let content = fs::read_to_string(&manifest)
+            .map_err(|e| format!("Failed to read '{}': {}", manifest.display(), e))?;
+        let mut campaign: Campaign = toml::from_str(&content)
+            .map_err(|e| format!("Invalid campaign manifest '{}': {}", manifest.display(), e))?;
+        campaign.dir = dir.as_ref().to_path_buf();
+        Ok(campaign)
+    }
+
+    /// The most recently created campaign under `campaigns/`, if any.
+    pub fn latest() -> Result<Option<Self>, Box<dyn Error>> {
+        let root = Path::new(CAMPAIGNS_DIR);
+        if !root.exists() {
+            return Ok(None);
+        }
+        let mut latest: Option<Campaign> = None;
+        for entry in fs::read_dir(root)? {
+            let dir = entry?.path();
+            if !dir.join(MANIFEST_FILE).exists() {
+                continue;
+            }
+            let campaign = Campaign::load(&dir)?;
+            if latest.as_ref().is_none_or(|l| campaign.created > l.created) {
+                latest = Some(campaign);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// The campaign of an instruction file or report. Files outside of a
+    /// campaign directory, ex: in the working directory, get a campaign
+    /// without manifest in the directory of the file.
+    pub fn containing<P: AsRef<Path>>(file: P) -> Result<Self, Box<dyn Error>> {
+        let file = file.as_ref();
+        let dir = match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if dir.join(MANIFEST_FILE).exists() {
+            return Campaign::load(dir);
+        }
+        let file_name = |p: &Path| {
+            p.file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        Ok(Campaign {
+            name: file
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            created: String::new(),
+            instruction_file: file_name(file),
+            report_file: String::new(),
+            firmware: None,
+            dir,
+        })
+    }
+
+    pub fn instruction_path(&self) -> PathBuf {
+        self.dir.join(&self.instruction_file)
+    }
+
+    pub fn report_path(&self) -> Option<PathBuf> {
+        (!self.report_file.is_empty()).then(|| self.dir.join(&self.report_file))
+    }
+
+    pub fn pcaps_dir(&self) -> PathBuf {
+        self.dir.join(PCAPS_DIR)
+    }
+
+    pub fn logs_dir(&self) -> PathBuf {
+        self.dir.join(LOGS_DIR)
+    }
+
+    pub fn config_dumps_dir(&self) -> PathBuf {
+        self.dir.join(CONFIG_DUMPS_DIR)
+    }
+
+    fn run_record_path(&self) -> PathBuf {
+        self.dir.join(RUN_RECORD_FILE)
+    }
+
+    /// Attempts of `test_id` recorded so far, oldest first.
+    pub fn attempts(&self, test_id: &str) -> Result<Vec<AttemptRecord>, Box<dyn Error>> {
+        let path = self.run_record_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        // Records written before notes and artifacts were kept have 4 fields
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+        let mut attempts = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            if record.get(1) != Some(test_id) {
+                continue;
+            }
+            let field = |idx: usize| record.get(idx).unwrap_or_default().to_string();
+            attempts.push(AttemptRecord {
+                timestamp: field(0),
+                attempt: field(2).parse().unwrap_or_default(),
+                outcome: field(3),
+                notes: field(4),
+                artifacts: field(5),
+            });
+        }
+        Ok(attempts)
+    }
+
+    /// Passed attempts out of the attempts that ran, ex: "2/3 passed", or
+    /// "2/4 passed, 1 not verified". Blocked and Skipped attempts are not
+    /// counted.
+    pub fn frequency(&self, test_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let attempts = self.attempts(test_id)?;
+        let ran: Vec<&AttemptRecord> = attempts
+            .iter()
+            .filter(|a| TestOutcome::ran(&a.outcome))
+            .collect();
+        if ran.is_empty() {
+            return Ok(None);
+        }
+        let count =
+            |outcome: TestOutcome| ran.iter().filter(|a| a.outcome == outcome.as_str()).count();
+        let passed = count(TestOutcome::Passed);
+        let not_verified = count(TestOutcome::NotVerified);
+        let mut frequency = format!("{}/{} passed", passed, ran.len());
+        if not_verified > 0 {
+            frequency.push_str(&format!(", {} not verified", not_verified));
+        }
+        Ok(Some(frequency))
+    }
+
+    /// Artifacts of attempt `attempt` of `test_id`. `report` is the report the
+    /// run updates.
+    pub fn test_artifacts(
+        &self,
+        test_id: &str,
+        attempt: u32,
+        report: Option<&Path>,
+    ) -> TestArtifacts {
+        TestArtifacts {
+            dir: self.pcaps_dir(),
+            config_dir: self.config_dumps_dir(),
+            name: format!("{}_attempt{}", file_name_safe(test_id), attempt),
+            firmware: self.firmware.clone(),
+            report: report.map(Path::to_path_buf),
+        }
+    }
+
+    /// Artifacts of the group setup/teardown of `test_group`. `report` is the
+    /// report the run updates.
+    pub fn group_artifacts(&self, test_group: &str, report: Option<&Path>) -> TestArtifacts {
+        TestArtifacts {
+            dir: self.pcaps_dir(),
+            config_dir: self.config_dumps_dir(),
+            name: format!("group_{}", file_name_safe(test_group)),
+            firmware: self.firmware.clone(),
+            report: report.map(Path::to_path_buf),
+        }
+    }
+
+    pub fn test_log_path(&self, artifacts: &TestArtifacts) -> PathBuf {
+        self.logs_dir().join(format!("{}.log", artifacts.name))
+    }
+
+    /// Append one attempt of a test to the run record, with its notes and
+    /// artifacts.
+    pub fn record_attempt(
+        &self,
+        test_id: &str,
+        attempt: u32,
+        outcome: &str,
+        notes: &str,
+        artifacts: &[PathBuf],
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.run_record_path();
+        let is_new = !path.exists();
+        let file: File = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut writer = csv::Writer::from_writer(file);
+        if is_new {
+            writer.write_record([
+                "Timestamp",
+                "Test ID",
+                "Attempt",
+                "Outcome",
+                "Notes",
+                "Artifacts",
+            ])?;
+        }
+        let artifacts: Vec<String> = artifacts
+            .iter()
+            .map(|a| a.strip_prefix(&self.dir).unwrap_or(a).display().to_string())
+            .collect();
+        writer.write_record([
+            Local::now().format(CREATED_FORMAT).to_string(),
+            test_id.to_string(),
+            attempt.to_string(),
+            outcome.to_string(),
+            notes.to_string(),
+            artifacts.join("; "),
+        ])?;
+        writer.flush()?;
+        Ok(())
+    }
+}
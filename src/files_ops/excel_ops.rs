@@ -57,11 +57,16 @@ pub fn format_excel_sheet<P: AsRef<Path>>(xlsx_path: P) -> Result<(), Box<dyn Er
     Ok(())
 }
 
+/// Set the status and notes of a report row. `log_path` links the test log
+/// from the Test ID cell and `frequency` fills the Frequency cell, ex:
+/// "2/3 passed".
 pub fn update_test_status<P: AsRef<Path>>(
     xlsx_path: P,
     test_id: &str,
     new_status: &str,
     notes: Option<&str>,
+    log_path: Option<&Path>,
+    frequency: Option<&str>,
 ) -> Result<(), Box<dyn Error>> {
     let script = script_path("excel_format.py")?;
 
@@ -72,8 +77,15 @@ pub fn update_test_status<P: AsRef<Path>>(
         .arg(test_id)
         .arg(new_status);
 
-    if let Some(n) = notes {
-        cmd.arg(n);
+    // An empty argument keeps the current cell when a later one is given
+    if notes.is_some() || log_path.is_some() || frequency.is_some() {
+        cmd.arg(notes.unwrap_or(""));
+    }
+    if log_path.is_some() || frequency.is_some() {
+        cmd.arg(log_path.unwrap_or(Path::new("")));
+    }
+    if let Some(frequency) = frequency {
+        cmd.arg(frequency);
     }
 
     let status = cmd.status()?;
@@ -86,18 +98,85 @@ pub fn update_test_status<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Set the value of metadata rows at the top of the report, ex:
+/// `("Firmware Version:", "1.2.3")`.
+pub fn update_report_metadata<P: AsRef<Path>>(
+    xlsx_path: P,
+    fields: &[(&str, &str)],
+) -> Result<(), Box<dyn Error>> {
+    let script = script_path("excel_format.py")?;
+
+    let mut cmd = Command::new("python");
+    cmd.arg(script).arg("metadata").arg(xlsx_path.as_ref());
+    for (label, value) in fields {
+        cmd.arg(label).arg(value);
+    }
+
+    let status = cmd.status()?;
+
+    if !status.success() {
+        return Err(format!(
+            "Failed to update the metadata of '{}'",
+            xlsx_path.as_ref().display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
 pub fn report_sheet_test_id_pass<P: AsRef<Path>>(
     xlsx_path: P,
     test_id: &str,
     notes: Option<&str>,
+    log_path: Option<&Path>,
+    frequency: Option<&str>,
 ) -> Result<(), Box<dyn Error>> {
-    update_test_status(xlsx_path, test_id, "Pass", notes)
+    update_test_status(xlsx_path, test_id, "Pass", notes, log_path, frequency)
 }
 
 pub fn report_sheet_test_id_fail<P: AsRef<Path>>(
     xlsx_path: P,
     test_id: &str,
     notes: Option<&str>,
+    log_path: Option<&Path>,
+    frequency: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    update_test_status(xlsx_path, test_id, "Fail", notes, log_path, frequency)
+}
+
+pub fn report_sheet_test_id_blocked<P: AsRef<Path>>(
+    xlsx_path: P,
+    test_id: &str,
+    notes: Option<&str>,
+    log_path: Option<&Path>,
+    frequency: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    update_test_status(xlsx_path, test_id, "Blocked", notes, log_path, frequency)
+}
+
+pub fn report_sheet_test_id_not_verified<P: AsRef<Path>>(
+    xlsx_path: P,
+    test_id: &str,
+    notes: Option<&str>,
+    log_path: Option<&Path>,
+    frequency: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    update_test_status(
+        xlsx_path,
+        test_id,
+        "Not verified",
+        notes,
+        log_path,
+        frequency,
+    )
+}
+
+pub fn report_sheet_test_id_skipped<P: AsRef<Path>>(
+    xlsx_path: P,
+    test_id: &str,
+    notes: Option<&str>,
+    log_path: Option<&Path>,
+    frequency: Option<&str>,
 ) -> Result<(), Box<dyn Error>> {
-    update_test_status(xlsx_path, test_id, "Fail", notes)
+    update_test_status(xlsx_path, test_id, "Skipped", notes, log_path, frequency)
 }
@@ -1,12 +1,25 @@
 use csv::Writer;
-use serde::Deserialize;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use toml::Value;
 
-#[derive(Debug, Deserialize)]
+use crate::files_ops::firmware_ops::{
+    FIRMWARE_FILE_LABEL, FIRMWARE_HASH_LABEL, FIRMWARE_VERSION_LABEL,
+};
+use crate::files_ops::instruction_file::{Group, InstructionFile};
+use crate::files_ops::selector_ops::TestFilter;
+use crate::files_ops::selector_ops::TestSelection;
+use crate::files_ops::snippet_ops::{Snippets, expand_snippets, substitute_params};
+use crate::supporters::misc::parse_duration;
+use crate::test_ops::test_ops_process_instructions::{InstructionEstimate, estimate_instructions};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Test {
     pub test_id: String,
     pub test_group: String,
@@ -15,82 +28,255 @@ pub struct Test {
     pub pass_condition: String,
     pub instructions: Vec<String>,
     pub test_authors_and_contact_persons: Vec<String>,
+    /// Instructions run before `instructions`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub setup: Vec<String>,
+    /// Instructions run after `instructions`, even if a step failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teardown: Vec<String>,
+    /// Test IDs that must not have failed earlier in the same run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    /// Free-form labels used to filter tests, ex: `tags = ["smoke", "long"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Expected run time, ex: `"30m"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_duration: Option<String>,
+    /// Expands the test into one variant per combination of values, ex:
+    /// `matrix = { pixel_format = [0, 2, 3] }` gives `1.1[pixel_format=0]`, ...
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub matrix: BTreeMap<String, Vec<Value>>,
+}
+
+impl Test {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Estimate derived from the setup, instructions and teardown.
+    pub fn instruction_estimate(&self) -> InstructionEstimate {
+        let lines: Vec<String> = self
+            .setup
+            .iter()
+            .chain(&self.instructions)
+            .chain(&self.teardown)
+            .cloned()
+            .collect();
+        estimate_instructions(&lines)
+    }
+
+    /// The `estimated_duration` field, or the estimate from the instructions
+    /// if the test has none.
+    pub fn estimated_duration(&self) -> Result<Duration, Box<dyn Error>> {
+        match self.estimated_duration.as_deref() {
+            Some(value) => {
+                parse_duration(value).map_err(|e| format!("Test '{}': {}", self.test_id, e).into())
+            }
+            None => Ok(self.instruction_estimate().duration),
+        }
+    }
+
+    /// Expand a `matrix` test into its variants, substituting the `{key}`
+    /// placeholders. A test without a matrix is returned as is.
+    fn expand_matrix(self) -> Result<Vec<Test>, Box<dyn Error>> {
+        if self.matrix.is_empty() {
+            return Ok(vec![self]);
+        }
+
+        let mut combinations: Vec<Vec<(String, String)>> = vec![Vec::new()];
+        for (key, values) in &self.matrix {
+            if values.is_empty() {
+                return Err(format!(
+                    "Test '{}': matrix key '{}' has no values",
+                    self.test_id, key
+                )
+                .into());
+            }
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut next = combination.clone();
+                        next.push((key.clone(), matrix_value_string(value)));
+                        next
+                    })
+                })
+                .collect();
+        }
+
+        let mut variants = Vec::with_capacity(combinations.len());
+        for combination in combinations {
+            let label = combination
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(",");
+            let variant_id = format!("{}[{}]", self.test_id, label);
+            let values: BTreeMap<String, String> = combination.into_iter().collect();
+            let substitute = |line: &String| substitute_params(line, &values, &variant_id);
+            let substitute_all = |lines: &[String]| -> Result<Vec<String>, Box<dyn Error>> {
+                lines.iter().map(substitute).collect()
+            };
+
+            variants.push(Test {
+                test_description: substitute(&self.test_description)?,
+                pass_condition: substitute(&self.pass_condition)?,
+                instructions: substitute_all(&self.instructions)?,
+                setup: substitute_all(&self.setup)?,
+                teardown: substitute_all(&self.teardown)?,
+                matrix: BTreeMap::new(),
+                test_id: variant_id.clone(),
+                ..self.clone()
+            });
+        }
+        Ok(variants)
+    }
+}
+
+fn matrix_value_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// ID of the test a matrix variant was generated from, ex: `1.1[pixel_format=0]` -> `1.1`.
+pub fn base_test_id(test_id: &str) -> &str {
+    test_id.split('[').next().unwrap_or(test_id)
+}
+
+/// True if `selector` is `test_id` itself or the test it was generated from.
+pub fn test_id_matches(selector: &str, test_id: &str) -> bool {
+    test_id == selector || base_test_id(test_id) == selector
+}
+
+/// Setup/teardown instructions shared by every test of a `test_group`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupHooks {
+    #[serde(default)]
+    pub setup: Vec<String>,
+    #[serde(default)]
+    pub teardown: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct TestList {
     test: Vec<Test>,
+    #[serde(default)]
+    groups: BTreeMap<String, GroupHooks>,
+    #[serde(default)]
+    snippets: Snippets,
+}
+
+impl TestList {
+    /// Read the base TOML, expand every snippet `include` and every matrix test.
+    fn load(input_path: &str) -> Result<Self, Box<dyn Error>> {
+        let toml_str = fs::read_to_string(input_path)?;
+        let mut test_list: TestList = toml::from_str(&toml_str)?;
+
+        for test in &mut test_list.test {
+            let matrix_keys: Vec<&String> = test.matrix.keys().collect();
+            let expand = |lines: &[String]| {
+                expand_snippets(lines, &test_list.snippets, &matrix_keys)
+                    .map_err(|e| format!("Test '{}': {}", test.test_id, e))
+            };
+            test.setup = expand(&test.setup)?;
+            test.instructions = expand(&test.instructions)?;
+            test.teardown = expand(&test.teardown)?;
+        }
+        for (test_group, hooks) in &mut test_list.groups {
+            let expand = |lines: &[String]| {
+                expand_snippets(lines, &test_list.snippets, &[])
+                    .map_err(|e| format!("Group '{}': {}", test_group, e))
+            };
+            hooks.setup = expand(&hooks.setup)?;
+            hooks.teardown = expand(&hooks.teardown)?;
+        }
+
+        // Matrix placeholders may come from snippets, so expand them last.
+        let mut expanded = Vec::with_capacity(test_list.test.len());
+        for test in test_list.test {
+            expanded.extend(test.expand_matrix()?);
+        }
+        test_list.test = expanded;
+
+        Ok(test_list)
+    }
 }
 
-/// Read the TOML at `input_path`, filter by `ids` and/or `priority`.
+/// Read the TOML at `input_path`, filter by `ids` selectors, `priority` and
+/// tags/duration.
 pub fn test_file_filter(
     input_path: &str,
     ids: &[String],
     priority: &Option<String>,
+    test_filter: &TestFilter,
 ) -> Result<Vec<Test>, Box<dyn Error>> {
     // Check if the file exists
     if !std::path::Path::new(input_path).exists() {
         return Err(format!("File does not exist: '{}' ", input_path).into());
     }
 
-    let toml_str = fs::read_to_string(input_path)?;
-    let test_list: TestList = toml::from_str(&toml_str)?;
-
-    let filtered = test_list
-        .test
-        .into_iter()
-        .filter(|t| {
-            let id_match = ids.is_empty() || ids.contains(&t.test_id);
-            let prio_match = match priority {
-                Some(p) => t.test_priority.eq_ignore_ascii_case(p),
-                None => true,
-            };
-            id_match && prio_match
-        })
-        .collect();
+    let test_list = TestList::load(input_path)?;
+    let selection = TestSelection::parse(ids)?;
+    selection.check_known(&test_list.test, input_path)?;
+
+    let mut filtered = Vec::new();
+    for t in test_list.test {
+        let id_match = selection.matches(&t);
+        let prio_match = match priority {
+            Some(p) => t.test_priority.eq_ignore_ascii_case(p),
+            None => true,
+        };
+        if id_match && prio_match && test_filter.matches(&t)? {
+            filtered.push(t);
+        }
+    }
 
     Ok(filtered)
 }
 
-/// Write a grouped TOML report, with each instruction and author on its own line.
+/// Read the `[groups."<test_group>"]` setup/teardown tables of the base TOML.
+pub fn read_group_hooks(input_path: &str) -> Result<BTreeMap<String, GroupHooks>, Box<dyn Error>> {
+    Ok(TestList::load(input_path)?.groups)
+}
+
+/// Read every test of the base TOML, snippets and matrix expanded.
+pub fn read_tests(input_path: &str) -> Result<Vec<Test>, Box<dyn Error>> {
+    Ok(TestList::load(input_path)?.test)
+}
+
+/// Write the grouped instruction file, keeping the group order of `groups`.
 pub fn export_grouped_toml<P: AsRef<Path>>(
     groups: &[(String, Vec<Test>)],
+    group_hooks: &BTreeMap<String, GroupHooks>,
     out_path: P,
 ) -> Result<PathBuf, Box<dyn Error>> {
-    let mut toml_buf = String::new();
-
-    for (label, tests) in groups {
-        // Top-level table for the group
-        toml_buf.push_str(&format!("[{}]\n\n", label));
-
-        for test in tests {
-            // Array-of-tables header
-            toml_buf.push_str(&format!("[[{}.test]]\n", label));
-
-            // Scalar fields
-            toml_buf.push_str(&format!("test_id = {:?}\n", test.test_id));
-            toml_buf.push_str(&format!("test_group = {:?}\n", test.test_group));
-            toml_buf.push_str(&format!("test_priority = {:?}\n", test.test_priority));
-            toml_buf.push_str(&format!("test_description = {:?}\n", test.test_description));
-            toml_buf.push_str(&format!("pass_condition = {:?}\n", test.pass_condition));
-
-            // Multi-line instructions array
-            toml_buf.push_str("instructions = [\n");
-            for instr in &test.instructions {
-                toml_buf.push_str(&format!("  {:?},\n", instr));
-            }
-            toml_buf.push_str("]\n");
+    // Only keep the group hooks of the test groups that made it into the export
+    let used_hooks = group_hooks
+        .iter()
+        .filter(|(test_group, _)| {
+            groups
+                .iter()
+                .any(|(_, tests)| tests.iter().any(|t| &t.test_group == *test_group))
+        })
+        .map(|(test_group, hooks)| (test_group.clone(), hooks.clone()))
+        .collect();
 
-            // Multi-line authors/contact array
-            toml_buf.push_str("test_authors_and_contact_persons = [\n");
-            for author in &test.test_authors_and_contact_persons {
-                toml_buf.push_str(&format!("  {:?},\n", author));
-            }
-            toml_buf.push_str("]\n\n");
-        }
-    }
+    let grouped: IndexMap<String, Group> = groups
+        .iter()
+        .map(|(label, tests)| {
+            (
+                label.clone(),
+                Group {
+                    test: tests.clone(),
+                },
+            )
+        })
+        .collect();
 
-    fs::write(&out_path, toml_buf)?;
+    InstructionFile::new(grouped, used_hooks).save(&out_path)?;
     println!("TOML report generated: {}", out_path.as_ref().display());
     Ok(out_path.as_ref().to_path_buf())
 }
@@ -99,37 +285,23 @@ pub fn export_grouped_csv<P: AsRef<Path>>(
     toml_path: P,
     output_path: P,
 ) -> Result<PathBuf, Box<dyn Error>> {
-    let toml_str = fs::read_to_string(&toml_path)?;
-    let root: Value = toml::from_str(&toml_str)?;
-
-    // 2. Collect groups
-    let mut groups: Vec<(String, Vec<Test>)> = Vec::new();
-    if let Value::Table(table) = root {
-        for (label, section) in table {
-            if let Some(Value::Array(arr)) = section.get("test") {
-                let tests = arr
-                    .clone()
-                    .into_iter()
-                    .map(|tbl| tbl.try_into())
-                    .collect::<Result<_, _>>()?;
-                groups.push((label, tests));
-            }
-        }
-    }
+    let instruction_file = InstructionFile::load(&toml_path)?;
 
     // 3. Open output CSV file
     let file = File::create(&output_path)?;
     let mut buf = BufWriter::new(file);
 
     // 4. Write fixed metadata header
-    for line in &[
-        "Technician Name:,",
-        "Firmware Type:,",
-        "Firmware Version:,",
-        "Sensor Serial Number:,",
-        "CCC Tool Version:,",
+    for label in &[
+        "Technician Name:",
+        "Firmware Type:",
+        FIRMWARE_VERSION_LABEL,
+        FIRMWARE_FILE_LABEL,
+        FIRMWARE_HASH_LABEL,
+        "Sensor Serial Number:",
+        "CCC Tool Version:",
     ] {
-        writeln!(buf, "{}", line)?;
+        writeln!(buf, "{},", label)?;
     }
     writeln!(buf)?; // blank
 
@@ -137,7 +309,7 @@ pub fn export_grouped_csv<P: AsRef<Path>>(
     let mut wtr = Writer::from_writer(buf);
 
     // 6. Emit each group in TOML order
-    for (label, tests) in groups {
+    for (label, group) in &instruction_file.groups {
         let mut buf = wtr.into_inner()?;
         writeln!(buf)?; // blank
         writeln!(buf, "{}", label)?; // section label
@@ -155,7 +327,7 @@ pub fn export_grouped_csv<P: AsRef<Path>>(
         ])?;
 
         // rows for this group
-        for t in tests {
+        for t in &group.test {
             wtr.write_record(&[
                 &t.test_id,
                 &t.test_group,
@@ -175,34 +347,75 @@ pub fn export_grouped_csv<P: AsRef<Path>>(
     Ok(output_path.as_ref().to_path_buf())
 }
 
-pub fn extract_test_ids<P: AsRef<Path>>(path: P) -> Result<Vec<String>, Box<dyn Error>> {
-    // Read the TOML file into a string
-    let toml_str = fs::read_to_string(path)?;
-
-    // Parse the string into a toml::Value
-    let root = toml_str.parse::<Value>()?;
-
-    let mut ids = Vec::new();
-
-    // Ensure the top-level is a table
-    if let Value::Table(tables) = root {
-        // Iterate over each top-level table (e.g. "1_POINT_CLOUD")
-        for (_, group_val) in tables {
-            if let Value::Table(group) = group_val {
-                // Look for an array-of-tables named "test"
-                if let Some(Value::Array(tests)) = group.get("test") {
-                    for test_val in tests {
-                        if let Value::Table(test_tbl) = test_val {
-                            // Extract the "test_id" field if it exists
-                            if let Some(Value::String(id)) = test_tbl.get("test_id") {
-                                ids.push(id.clone());
-                            }
-                        }
-                    }
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(extra: &str) -> Test {
+        toml::from_str(&format!(
+            r#"
+test_id = "1.5"
+test_group = "1:POINT_CLOUD"
+test_priority = "HIGH"
+test_description = "Check format {{pixel_format}} at {{rate}} Hz"
+pass_condition = "Points in format {{pixel_format}}"
+instructions = ["ccc set pixel_format={{pixel_format}},rate={{rate}} --force", "wait_e"]
+teardown = ["ccc set pixel_format=0 --force"]
+test_authors_and_contact_persons = ["TBD"]
+{}
+"#,
+            extra
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_without_matrix_is_kept() {
+        let plain = test("");
+        let expanded = plain.clone().expand_matrix().unwrap();
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0].test_id, "1.5");
+        assert_eq!(expanded[0].instructions, plain.instructions);
+    }
+
+    #[test]
+    fn matrix_expands_every_combination() {
+        let variants = test(r#"matrix = { pixel_format = [0, 2], rate = ["10", 20.5] }"#)
+            .expand_matrix()
+            .unwrap();
+        let ids: Vec<&str> = variants.iter().map(|t| t.test_id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "1.5[pixel_format=0,rate=10]",
+                "1.5[pixel_format=0,rate=20.5]",
+                "1.5[pixel_format=2,rate=10]",
+                "1.5[pixel_format=2,rate=20.5]",
+            ]
+        );
+        let last = &variants[3];
+        assert_eq!(last.test_description, "Check format 2 at 20.5 Hz");
+        assert_eq!(last.pass_condition, "Points in format 2");
+        assert_eq!(
+            last.instructions[0],
+            "ccc set pixel_format=2,rate=20.5 --force"
+        );
+        assert_eq!(last.teardown, ["ccc set pixel_format=0 --force"]);
+        assert!(last.matrix.is_empty());
+        assert_eq!(base_test_id(&last.test_id), "1.5");
+        assert!(test_id_matches("1.5", &last.test_id));
+        assert!(!test_id_matches("1.5.1", &last.test_id));
     }
 
-    Ok(ids)
+    #[test]
+    fn matrix_errors() {
+        let err = test("matrix = { pixel_format = [] }")
+            .expand_matrix()
+            .unwrap_err();
+        assert!(err.to_string().contains("has no values"));
+        let err = test("matrix = { pixel_format = [0] }")
+            .expand_matrix()
+            .unwrap_err();
+        assert!(err.to_string().contains("Missing parameter 'rate'"));
+    }
 }
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::files_ops::excel_ops::update_report_metadata;
+
+// Report metadata rows filled with the firmware under test
+pub const FIRMWARE_VERSION_LABEL: &str = "Firmware Version:";
+pub const FIRMWARE_FILE_LABEL: &str = "Firmware File:";
+pub const FIRMWARE_HASH_LABEL: &str = "Firmware SHA-256:";
+
+/// A firmware image and the SHA-256 of its content, ex: the image pinned for
+/// a campaign with `vtg test --firmware ultra.cepbin`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareImage {
+    /// Path of the image, as given.
+    pub file: String,
+    /// Lowercase hex SHA-256, as printed by `sha256sum`.
+    pub sha256: String,
+}
+
+impl FirmwareImage {
+    /// Read and hash the image at `file`.
+    pub fn from_file(file: &str) -> Result<Self, Box<dyn Error>> {
+        let content =
+            fs::read(file).map_err(|e| format!("Failed to read firmware '{}': {}", file, e))?;
+        let sha256 = Sha256::digest(&content)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok(FirmwareImage {
+            file: file.to_string(),
+            sha256,
+        })
+    }
+
+    /// File name of the image, without its directory.
+    pub fn file_name(&self) -> String {
+        Path::new(&self.file)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.file.clone())
+    }
+
+    /// Fail if the image changed on disk since it was hashed.
+    pub fn verify(&self) -> Result<(), Box<dyn Error>> {
+        let current = FirmwareImage::from_file(&self.file)?;
+        if current.sha256 != self.sha256 {
+            return Err(format!(
+                "Firmware '{}' changed since it was pinned: SHA-256 {} instead of {}",
+                self.file, current.sha256, self.sha256
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Write the image file name, hash and, if known, the version the sensor
+    /// reports into the report metadata rows.
+    pub fn record_in_report(
+        &self,
+        xlsx_path: &Path,
+        version: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let file_name = self.file_name();
+        let mut fields = vec![
+            (FIRMWARE_FILE_LABEL, file_name.as_str()),
+            (FIRMWARE_HASH_LABEL, self.sha256.as_str()),
+        ];
+        fields.extend(version.map(|v| (FIRMWARE_VERSION_LABEL, v)));
+        update_report_metadata(xlsx_path, &fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_hashed_like_sha256sum() {
+        let path = std::env::temp_dir().join(format!("vtg_firmware_{}.cepbin", std::process::id()));
+        fs::write(&path, b"abc").unwrap();
+        let image = FirmwareImage::from_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            image.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        image.verify().unwrap();
+
+        fs::write(&path, b"abd").unwrap();
+        let error = image.verify().unwrap_err().to_string();
+        let _ = fs::remove_file(&path);
+        assert!(error.contains("changed since it was pinned"), "{}", error);
+    }
+}
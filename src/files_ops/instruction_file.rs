@@ -0,0 +1,287 @@
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::files_ops::file_ops::{GroupHooks, Test};
+
+/// Current layout of the grouped instruction file. Bump it together with a
+/// migration step in `InstructionFile::migrate` when the layout changes.
+pub const INSTRUCTION_FILE_SCHEMA_VERSION: u32 = 1;
+
+// Files written before the version field existed.
+const LEGACY_SCHEMA_VERSION: u32 = 0;
+
+fn legacy_schema_version() -> u32 {
+    LEGACY_SCHEMA_VERSION
+}
+
+/// Top-level keys of the file, a group can't use them as its label.
+pub const RESERVED_GROUP_LABELS: [&str; 2] = ["schema_version", "group_hooks"];
+
+fn reserved_label_error(label: &str) -> Box<dyn Error> {
+    format!(
+        "Group label '{}' is reserved, rename the group (reserved: {})",
+        label,
+        RESERVED_GROUP_LABELS.join(", ")
+    )
+    .into()
+}
+
+/// Serde reads a group under a reserved label as the field itself, find it in
+/// the raw table instead: `schema_version` as a table or `group_hooks` with a
+/// `[[group_hooks.test]]` array.
+fn find_reserved_group(table: &toml::Table) -> Option<&'static str> {
+    RESERVED_GROUP_LABELS
+        .into_iter()
+        .find(|label| match table.get(*label) {
+            Some(toml::Value::Table(t)) => {
+                *label == "schema_version" || t.get("test").is_some_and(toml::Value::is_array)
+            }
+            _ => false,
+        })
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Group {
+    #[serde(default)]
+    pub test: Vec<Test>,
+}
+
+/// Grouped instruction file generated by `id-groups`/`priority` and consumed
+/// by `test` and `excel`. Each top-level table other than the fields below is
+/// a group label, kept in file order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstructionFile {
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub group_hooks: BTreeMap<String, GroupHooks>,
+    #[serde(flatten)]
+    pub groups: IndexMap<String, Group>,
+}
+
+impl InstructionFile {
+    pub fn new(groups: IndexMap<String, Group>, group_hooks: BTreeMap<String, GroupHooks>) -> Self {
+        InstructionFile {
+            schema_version: INSTRUCTION_FILE_SCHEMA_VERSION,
+            group_hooks,
+            groups,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(&path)?;
+        let invalid = |e: &dyn std::fmt::Display| {
+            format!(
+                "Invalid instruction file '{}': {}",
+                path.as_ref().display(),
+                e
+            )
+        };
+        let table: toml::Table = toml::from_str(&content).map_err(|e| invalid(&e))?;
+        if let Some(label) = find_reserved_group(&table) {
+            return Err(invalid(&reserved_label_error(label)).into());
+        }
+        // Parsed again from the text, `toml::Table` doesn't keep the group order
+        let file: InstructionFile = toml::from_str(&content).map_err(|e| invalid(&e))?;
+        file.migrate()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        if let Some(label) = self
+            .groups
+            .keys()
+            .find(|label| RESERVED_GROUP_LABELS.contains(&label.as_str()))
+        {
+            return Err(reserved_label_error(label));
+        }
+        fs::write(&path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Bring an older file up to the current schema.
+    fn migrate(mut self) -> Result<Self, Box<dyn Error>> {
+        if self.schema_version > INSTRUCTION_FILE_SCHEMA_VERSION {
+            return Err(format!(
+                "Instruction file schema version {} is newer than the supported version {}, update vtg",
+                self.schema_version, INSTRUCTION_FILE_SCHEMA_VERSION
+            )
+            .into());
+        }
+        // Version 0 -> 1: only the version field was added.
+        self.schema_version = INSTRUCTION_FILE_SCHEMA_VERSION;
+        Ok(self)
+    }
+
+    /// Every test with the label of its group, in file order.
+    pub fn tests(&self) -> impl Iterator<Item = (&str, &Test)> {
+        self.groups
+            .iter()
+            .flat_map(|(label, group)| group.test.iter().map(move |t| (label.as_str(), t)))
+    }
+
+    pub fn find_test(&self, test_id: &str) -> Option<(&str, &Test)> {
+        self.tests().find(|(_, t)| t.test_id == test_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn test(test_id: &str, instructions: &[&str]) -> Test {
+        Test {
+            test_id: test_id.to_string(),
+            test_group: "group".to_string(),
+            test_priority: "1".to_string(),
+            test_description: "description".to_string(),
+            pass_condition: "condition".to_string(),
+            instructions: instructions.iter().map(|i| i.to_string()).collect(),
+            test_authors_and_contact_persons: Vec::new(),
+            setup: Vec::new(),
+            teardown: Vec::new(),
+            requires: Vec::new(),
+            tags: Vec::new(),
+            estimated_duration: None,
+            matrix: BTreeMap::new(),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("vtg_{}_{}.toml", name, std::process::id()))
+    }
+
+    fn load_str(name: &str, content: &str) -> Result<InstructionFile, Box<dyn Error>> {
+        let path = temp_path(name);
+        fs::write(&path, content).unwrap();
+        let file = InstructionFile::load(&path);
+        fs::remove_file(&path).unwrap();
+        file
+    }
+
+    #[test]
+    fn groups_keep_file_order() {
+        let content = r#"
+schema_version = 1
+
+[[zeta.test]]
+test_id = "3"
+test_group = "zeta"
+test_priority = "1"
+test_description = ""
+pass_condition = ""
+instructions = []
+test_authors_and_contact_persons = []
+
+[[alpha.test]]
+test_id = "1"
+test_group = "alpha"
+test_priority = "1"
+test_description = ""
+pass_condition = ""
+instructions = []
+test_authors_and_contact_persons = []
+"#;
+        let file = load_str("order", content).unwrap();
+        let labels: Vec<&str> = file.groups.keys().map(String::as_str).collect();
+        assert_eq!(labels, ["zeta", "alpha"]);
+        let ids: Vec<&str> = file.tests().map(|(_, t)| t.test_id.as_str()).collect();
+        assert_eq!(ids, ["3", "1"]);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let instruction = r#"ccc set name="caméra \\ 1" --force"#;
+        let mut groups = IndexMap::new();
+        groups.insert(
+            "second".to_string(),
+            Group {
+                test: vec![test("2", &[instruction])],
+            },
+        );
+        groups.insert(
+            "first".to_string(),
+            Group {
+                test: vec![test("1", &["wait 1s"])],
+            },
+        );
+        let mut group_hooks = BTreeMap::new();
+        group_hooks.insert(
+            "second".to_string(),
+            GroupHooks {
+                setup: vec!["ccc reboot".to_string()],
+                teardown: Vec::new(),
+            },
+        );
+        let file = InstructionFile::new(groups, group_hooks);
+
+        let path = temp_path("round_trip");
+        file.save(&path).unwrap();
+        let loaded = InstructionFile::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.schema_version, INSTRUCTION_FILE_SCHEMA_VERSION);
+        let labels: Vec<&str> = loaded.groups.keys().map(String::as_str).collect();
+        assert_eq!(labels, ["second", "first"]);
+        let (label, test) = loaded.find_test("2").unwrap();
+        assert_eq!(label, "second");
+        assert_eq!(test.instructions, [instruction]);
+        assert_eq!(loaded.group_hooks["second"].setup, ["ccc reboot"]);
+    }
+
+    #[test]
+    fn legacy_file_is_migrated() {
+        let file = load_str("legacy", "").unwrap();
+        assert_eq!(file.schema_version, INSTRUCTION_FILE_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn newer_schema_is_rejected() {
+        let content = format!("schema_version = {}", INSTRUCTION_FILE_SCHEMA_VERSION + 1);
+        let error = load_str("newer", &content).unwrap_err().to_string();
+        assert!(
+            error.contains("newer than the supported version"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn reserved_labels_are_rejected() {
+        for label in RESERVED_GROUP_LABELS {
+            let mut groups = IndexMap::new();
+            groups.insert(
+                label.to_string(),
+                Group {
+                    test: vec![test("1", &[])],
+                },
+            );
+            let path = temp_path(&format!("reserved_{}", label));
+            let error = InstructionFile::new(groups, BTreeMap::new())
+                .save(&path)
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("is reserved"), "{}", error);
+            assert!(!path.exists());
+
+            let content = format!(
+                "[[{}.test]]\ntest_id = \"1\"\ntest_group = \"g\"\ntest_priority = \"1\"\n\
+                 test_description = \"\"\npass_condition = \"\"\ninstructions = []\n\
+                 test_authors_and_contact_persons = []\n",
+                label
+            );
+            let error = load_str(&format!("reserved_{}", label), &content)
+                .unwrap_err()
+                .to_string();
+            assert!(
+                error.contains(&format!("'{}' is reserved", label)),
+                "{}",
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+use crate::commands::commands_config::{APPLY_PROFILE_KEYWORD, RESTORE_BASELINE_KEYWORD};
+use crate::files_ops::file_ops::{GroupHooks, Test, read_group_hooks, read_tests};
+use crate::files_ops::instruction_file::InstructionFile;
+use crate::files_ops::profile_ops::Profile;
+
+/// A problem found by `vtg lint`, ex: a missing profile.
+#[derive(Debug, Clone)]
+pub struct LintIssue {
+    /// Where the line is, ex: `test 1.5.1 instructions`.
+    pub location: String,
+    pub line: String,
+    pub message: String,
+}
+
+/// Instruction lines of every test and group hook, with where they are.
+fn instruction_sections<'a>(
+    tests: impl Iterator<Item = &'a Test>,
+    group_hooks: &'a BTreeMap<String, GroupHooks>,
+) -> Vec<(String, &'a [String])> {
+    let mut sections = Vec::new();
+    for t in tests {
+        sections.push((format!("test {} setup", t.test_id), &t.setup[..]));
+        sections.push((
+            format!("test {} instructions", t.test_id),
+            &t.instructions[..],
+        ));
+        sections.push((format!("test {} teardown", t.test_id), &t.teardown[..]));
+    }
+    for (test_group, hooks) in group_hooks {
+        sections.push((format!("group {} setup", test_group), &hooks.setup[..]));
+        sections.push((
+            format!("group {} teardown", test_group),
+            &hooks.teardown[..],
+        ));
+    }
+    sections
+}
+
+/// Profile named by an `apply_profile` line, or by a `restore_baseline` line
+/// whose argument is a bare name, ex: not `baseline.txt` or `dumps/baseline`.
+/// Names given by a `$variable` are only known when the test runs.
+fn profile_reference(line: &str) -> Option<Result<&str, String>> {
+    let mut fields = line.split_whitespace().filter(|f| !f.starts_with("batch="));
+    let keyword = fields.next()?;
+    let name = fields.next();
+    match (keyword, name) {
+        (APPLY_PROFILE_KEYWORD, None) => Some(Err("Missing the profile name".to_string())),
+        (APPLY_PROFILE_KEYWORD, Some(name)) if !name.contains('$') => Some(Ok(name)),
+        (RESTORE_BASELINE_KEYWORD, Some(name)) if !name.contains(['$', '.', '/', '\\']) => {
+            Some(Ok(name))
+        }
+        _ => None,
+    }
+}
+
+fn lint_sections(sections: &[(String, &[String])]) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    for (location, lines) in sections {
+        for line in lines.iter().map(|l| l.trim()) {
+            let message = match profile_reference(line) {
+                Some(Ok(name)) => match Profile::load(name) {
+                    Ok(_) => continue,
+                    Err(e) => e.to_string(),
+                },
+                Some(Err(message)) => message,
+                None => continue,
+            };
+            issues.push(LintIssue {
+                location: location.clone(),
+                line: line.to_string(),
+                message,
+            });
+        }
+    }
+    issues
+}
+
+/// Check a base test list or a generated instruction file, ex: that every
+/// profile it applies exists in `profiles/` and is valid.
+pub fn lint_file(path: &str) -> Result<Vec<LintIssue>, Box<dyn Error>> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+    let table: toml::Table =
+        toml::from_str(&content).map_err(|e| format!("Invalid TOML '{}': {}", path, e))?;
+
+    // Base test lists have a top level `[[test]]` array, instruction files
+    // have their tests under group tables.
+    if table.contains_key("test") {
+        let (tests, group_hooks) = (read_tests(path)?, read_group_hooks(path)?);
+        Ok(lint_sections(&instruction_sections(
+            tests.iter(),
+            &group_hooks,
+        )))
+    } else {
+        let instruction_file = InstructionFile::load(path)?;
+        Ok(lint_sections(&instruction_sections(
+            instruction_file.tests().map(|(_, t)| t),
+            &instruction_file.group_hooks,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names_are_found() {
+        assert_eq!(
+            profile_reference("apply_profile stdv_roi_wide batch=4"),
+            Some(Ok("stdv_roi_wide"))
+        );
+        assert_eq!(
+            profile_reference("restore_baseline factory batch=2"),
+            Some(Ok("factory"))
+        );
+        assert!(matches!(
+            profile_reference("apply_profile batch=4"),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn files_and_variables_are_not_profiles() {
+        for line in [
+            "restore_baseline",
+            "restore_baseline baseline.txt",
+            "restore_baseline fields.toml batch=4",
+            "restore_baseline config_dumps/baseline",
+            "restore_baseline $dump",
+            "apply_profile $profile",
+            "## apply_profile stdv_roi_wide ##",
+            "ccc set roi_enable=1 --force",
+        ] {
+            assert_eq!(profile_reference(line), None, "{}", line);
+        }
+    }
+}
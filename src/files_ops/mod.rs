@@ -1,3 +1,10 @@
+pub mod campaign_ops;
 pub mod email_ops;
 pub mod excel_ops;
 pub mod file_ops;
+pub mod firmware_ops;
+pub mod instruction_file;
+pub mod lint_ops;
+pub mod profile_ops;
+pub mod selector_ops;
+pub mod snippet_ops;
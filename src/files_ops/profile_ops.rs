@@ -0,0 +1,63 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::supporters::sensor_config::{ConfigValues, parse_config_toml, set_assignment};
+
+// Directory of the named profiles, looked up in the working directory.
+pub const PROFILES_DIR: &str = "profiles";
+const PROFILE_EXTENSION: &str = "toml";
+
+/// A named set of sensor fields, `profiles/<name>.toml`, ex:
+/// `profiles/stdv_roi_wide.toml` holding `horiz_fov_left = -2185698`.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub fields: ConfigValues,
+}
+
+fn is_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// Path of the profile `name`, whether it exists or not.
+pub fn profile_path(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if !is_profile_name(name) {
+        return Err(format!(
+            "Invalid profile name '{}', use letters, digits, _ and -",
+            name
+        )
+        .into());
+    }
+    Ok(Path::new(PROFILES_DIR).join(format!("{}.{}", name, PROFILE_EXTENSION)))
+}
+
+impl Profile {
+    /// Load and validate the profile `name`: every field must be settable
+    /// with `ccc set`.
+    pub fn load(name: &str) -> Result<Self, Box<dyn Error>> {
+        let path = profile_path(name)?;
+        if !path.exists() {
+            return Err(format!("Profile '{}' not found: {}", name, path.display()).into());
+        }
+        let content = fs::read_to_string(&path)?;
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|e| format!("Invalid profile '{}': {}", path.display(), e))?;
+        let fields = parse_config_toml(&table)
+            .map_err(|e| format!("Invalid profile '{}': {}", path.display(), e))?;
+        if fields.is_empty() {
+            return Err(format!("Profile '{}' has no fields", path.display()).into());
+        }
+        for (field, value) in &fields {
+            set_assignment(field, value)
+                .map_err(|e| format!("Invalid profile '{}': {}", path.display(), e))?;
+        }
+        Ok(Profile {
+            name: name.to_string(),
+            fields,
+        })
+    }
+}
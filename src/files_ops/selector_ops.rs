@@ -0,0 +1,370 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::time::Duration;
+
+use crate::files_ops::file_ops::{Test, base_test_id, test_id_matches};
+use crate::supporters::misc::parse_duration;
+
+const EXCLUDE_PREFIX: char = '!';
+const WILDCARD_SUFFIX: &str = ".*";
+const GROUP_PREFIX: &str = "group:";
+const PRIORITY_PREFIX: &str = "priority:";
+
+/// One test selector as written on the command line.
+#[derive(Debug, Clone)]
+enum Selector {
+    /// `1.5`, also selects the variants of a matrix test.
+    Id(String),
+    /// `1.*`, `1.5.*`: every test under that ID.
+    Prefix(String),
+    /// `2.1-2.6`: every test ID between both ends, inclusive.
+    Range(Vec<u32>, Vec<u32>),
+    /// `group:NETWORKING`, `group:6` or `group:6:NETWORKING`.
+    Group(String),
+    /// `priority:HIGH`.
+    Priority(String),
+}
+
+impl Selector {
+    fn parse(raw: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(group) = strip_prefix_ignore_case(raw, GROUP_PREFIX) {
+            return Ok(Selector::Group(group.to_string()));
+        }
+        if let Some(priority) = strip_prefix_ignore_case(raw, PRIORITY_PREFIX) {
+            return Ok(Selector::Priority(priority.to_string()));
+        }
+        if let Some(prefix) = raw.strip_suffix(WILDCARD_SUFFIX) {
+            return Ok(Selector::Prefix(prefix.to_string()));
+        }
+        // Matrix variant IDs may hold a '-' in their values
+        if let Some((start, end)) = raw.split_once('-').filter(|_| !raw.contains('[')) {
+            let parse_end = |id: &str| {
+                id_components(id)
+                    .ok_or_else(|| format!("Invalid test ID range '{}', expected ex: 2.1-2.6", raw))
+            };
+            return Ok(Selector::Range(parse_end(start)?, parse_end(end)?));
+        }
+        if raw.is_empty() {
+            return Err("Empty test selector".into());
+        }
+        Ok(Selector::Id(raw.to_string()))
+    }
+
+    fn matches(&self, test: &Test) -> bool {
+        match self {
+            Selector::Id(id) => test_id_matches(id, &test.test_id),
+            Selector::Prefix(prefix) => {
+                let id = base_test_id(&test.test_id);
+                id == prefix
+                    || id
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+            }
+            Selector::Range(start, end) => match id_components(base_test_id(&test.test_id)) {
+                Some(id) => {
+                    compare_components(&id, start) != Ordering::Less
+                        && compare_components(&id, end) != Ordering::Greater
+                }
+                None => false,
+            },
+            Selector::Group(group) => {
+                test.test_group.eq_ignore_ascii_case(group)
+                    || test
+                        .test_group
+                        .split(':')
+                        .any(|part| part.eq_ignore_ascii_case(group))
+            }
+            Selector::Priority(priority) => test.test_priority.eq_ignore_ascii_case(priority),
+        }
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(raw: &'a str, prefix: &str) -> Option<&'a str> {
+    raw.get(..prefix.len())
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &raw[prefix.len()..])
+}
+
+fn id_components(id: &str) -> Option<Vec<u32>> {
+    id.split('.').map(|part| part.parse().ok()).collect()
+}
+
+// A parent ID sorts before its children, ex: 2.5 < 2.5.1 < 2.6.
+fn compare_components(a: &[u32], b: &[u32]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| x.cmp(y))
+        .find(|o| o.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+/// Test selection shared by `id-groups`, `priority` and `test`, ex:
+/// `1.*`, `1.5.*`, `2.1-2.6`, `group:NETWORKING`, `priority:HIGH`, `!6.8`.
+/// Selectors starting with `!` exclude tests. With only exclusions, every
+/// other test is selected.
+#[derive(Debug, Clone, Default)]
+pub struct TestSelection {
+    include: Vec<(String, Selector)>,
+    exclude: Vec<(String, Selector)>,
+}
+
+impl TestSelection {
+    pub fn parse<S: AsRef<str>>(selectors: &[S]) -> Result<Self, Box<dyn Error>> {
+        let mut selection = TestSelection::default();
+        for raw in selectors {
+            let raw = raw.as_ref().trim();
+            match raw.strip_prefix(EXCLUDE_PREFIX) {
+                Some(excluded) => selection
+                    .exclude
+                    .push((raw.to_string(), Selector::parse(excluded)?)),
+                None => selection
+                    .include
+                    .push((raw.to_string(), Selector::parse(raw)?)),
+            }
+        }
+        Ok(selection)
+    }
+
+    pub fn matches(&self, test: &Test) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|(_, s)| s.matches(test));
+        included && !self.exclude.iter().any(|(_, s)| s.matches(test))
+    }
+
+    /// Error listing the include selectors that match none of `tests`. Unknown
+    /// exclusions only warn, they cannot change what runs.
+    pub fn check_known<'a, 's, I>(&'s self, tests: I, source: &str) -> Result<(), Box<dyn Error>>
+    where
+        I: IntoIterator<Item = &'a Test> + Clone,
+    {
+        let unknown = |selectors: &'s [(String, Selector)]| -> Vec<&'s str> {
+            selectors
+                .iter()
+                .filter(|(_, s)| !tests.clone().into_iter().any(|t| s.matches(t)))
+                .map(|(raw, _)| raw.as_str())
+                .collect()
+        };
+
+        let unknown_excluded = unknown(&self.exclude);
+        if !unknown_excluded.is_empty() {
+            print_warn_ln!(
+                "Excluded test ID(s) not found in '{}': {}",
+                source,
+                unknown_excluded.join(", ")
+            );
+        }
+
+        let unknown_included = unknown(&self.include);
+        if unknown_included.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unknown test ID(s) in '{}': {}",
+                source,
+                unknown_included.join(", ")
+            )
+            .into())
+        }
+    }
+
+    /// Selected tests, in the order of the include selectors, then file order.
+    pub fn select<'a, I>(&self, tests: I) -> Vec<&'a Test>
+    where
+        I: IntoIterator<Item = &'a Test> + Clone,
+    {
+        let mut selected: Vec<&Test> = Vec::new();
+        let mut push = |test: &'a Test| {
+            if self.matches(test) && !selected.iter().any(|t| t.test_id == test.test_id) {
+                selected.push(test);
+            }
+        };
+        if self.include.is_empty() {
+            tests.into_iter().for_each(&mut push);
+        } else {
+            for (_, selector) in &self.include {
+                tests
+                    .clone()
+                    .into_iter()
+                    .filter(|t| selector.matches(t))
+                    .for_each(&mut push);
+            }
+        }
+        selected
+    }
+}
+
+/// Tag and duration filters shared by `id-groups`, `priority` and `test`.
+#[derive(Debug, Clone, Default)]
+pub struct TestFilter {
+    /// Keep tests with at least one of these tags, all tests if empty.
+    pub tags: Vec<String>,
+    /// Drop tests with any of these tags.
+    pub exclude_tags: Vec<String>,
+    /// Drop tests estimated to run longer than this.
+    pub max_duration: Option<Duration>,
+}
+
+impl TestFilter {
+    pub fn new(
+        tags: &[String],
+        exclude_tags: &[String],
+        max_duration: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(TestFilter {
+            tags: tags.to_vec(),
+            exclude_tags: exclude_tags.to_vec(),
+            max_duration: max_duration.map(parse_duration).transpose()?,
+        })
+    }
+
+    pub fn matches(&self, test: &Test) -> Result<bool, Box<dyn Error>> {
+        if !self.tags.is_empty() && !self.tags.iter().any(|t| test.has_tag(t)) {
+            return Ok(false);
+        }
+        if self.exclude_tags.iter().any(|t| test.has_tag(t)) {
+            return Ok(false);
+        }
+        match self.max_duration {
+            Some(max) => Ok(test.estimated_duration()? <= max),
+            None => Ok(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(test_id: &str, test_group: &str, test_priority: &str, extra: &str) -> Test {
+        toml::from_str(&format!(
+            r#"
+test_id = "{}"
+test_group = "{}"
+test_priority = "{}"
+test_description = ""
+pass_condition = ""
+instructions = []
+test_authors_and_contact_persons = []
+{}
+"#,
+            test_id, test_group, test_priority, extra
+        ))
+        .unwrap()
+    }
+
+    fn tests() -> Vec<Test> {
+        vec![
+            test("1.1", "1:POINT_CLOUD", "HIGH", ""),
+            test("1.5[pixel_format=0]", "1:POINT_CLOUD", "LOW", ""),
+            test("1.5[pixel_format=2]", "1:POINT_CLOUD", "LOW", ""),
+            test("1.5.1", "1:POINT_CLOUD", "LOW", ""),
+            test("1.10", "1:POINT_CLOUD", "LOW", ""),
+            test("2.5", "2:SENSOR_INFO", "MEDIUM", ""),
+            test("2.6", "2:SENSOR_INFO", "HIGH", ""),
+            test("6.1", "6:NETWORKING", "LOW", ""),
+        ]
+    }
+
+    fn selected(selectors: &[&str]) -> Vec<String> {
+        let tests = tests();
+        TestSelection::parse(selectors)
+            .unwrap()
+            .select(tests.iter())
+            .into_iter()
+            .map(|t| t.test_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn parse_selectors() {
+        assert!(matches!(Selector::parse("1.5").unwrap(), Selector::Id(id) if id == "1.5"));
+        assert!(matches!(Selector::parse("1.*").unwrap(), Selector::Prefix(p) if p == "1"));
+        assert!(matches!(
+            Selector::parse("2.1-2.6").unwrap(),
+            Selector::Range(start, end) if start == [2, 1] && end == [2, 6]
+        ));
+        assert!(matches!(Selector::parse("GROUP:net").unwrap(), Selector::Group(g) if g == "net"));
+        assert!(matches!(
+            Selector::parse("priority:HIGH").unwrap(),
+            Selector::Priority(p) if p == "HIGH"
+        ));
+        assert!(matches!(
+            Selector::parse("1.5[ip=a-b]").unwrap(),
+            Selector::Id(id) if id == "1.5[ip=a-b]"
+        ));
+        assert!(Selector::parse("2.x-2.6").is_err());
+        assert!(Selector::parse("").is_err());
+        assert!(TestSelection::parse(&["!"]).is_err());
+    }
+
+    #[test]
+    fn compare_parent_before_children() {
+        assert_eq!(compare_components(&[2, 5], &[2, 5, 1]), Ordering::Less);
+        assert_eq!(compare_components(&[2, 5, 1], &[2, 6]), Ordering::Less);
+        assert_eq!(compare_components(&[1, 10], &[1, 9]), Ordering::Greater);
+        assert_eq!(compare_components(&[3], &[3]), Ordering::Equal);
+    }
+
+    #[test]
+    fn select_ids_prefixes_and_ranges() {
+        assert_eq!(
+            selected(&["1.5"]),
+            ["1.5[pixel_format=0]", "1.5[pixel_format=2]"]
+        );
+        assert_eq!(selected(&["1.5[pixel_format=2]"]), ["1.5[pixel_format=2]"]);
+        assert_eq!(
+            selected(&["1.5.*"]),
+            ["1.5[pixel_format=0]", "1.5[pixel_format=2]", "1.5.1"]
+        );
+        // 1.5.1 sorts after the end of the range, 1.10 after 1.5
+        assert_eq!(
+            selected(&["1.1-1.5"]),
+            ["1.1", "1.5[pixel_format=0]", "1.5[pixel_format=2]"]
+        );
+        assert_eq!(selected(&["2.5-2.6", "1.1"]), ["2.5", "2.6", "1.1"]);
+    }
+
+    #[test]
+    fn select_groups_priorities_and_exclusions() {
+        assert_eq!(selected(&["group:networking"]), ["6.1"]);
+        assert_eq!(selected(&["group:2"]), ["2.5", "2.6"]);
+        assert_eq!(selected(&["group:2:SENSOR_INFO"]), ["2.5", "2.6"]);
+        assert_eq!(selected(&["priority:high"]), ["1.1", "2.6"]);
+        assert_eq!(selected(&["1.*", "!1.5"]), ["1.1", "1.5.1", "1.10"]);
+        assert_eq!(selected(&["!1.*", "!2.5"]), ["2.6", "6.1"]);
+        // A test matched by several selectors is only selected once
+        assert_eq!(selected(&["2.6", "priority:HIGH"]), ["2.6", "1.1"]);
+        assert_eq!(selected(&[]).len(), tests().len());
+    }
+
+    #[test]
+    fn unknown_selectors() {
+        let tests = tests();
+        let selection = TestSelection::parse(&["1.1", "9.*", "!8.8"]).unwrap();
+        let err = selection.check_known(tests.iter(), "list").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown test ID(s) in 'list': 9.*");
+        let selection = TestSelection::parse(&["1.1", "!8.8"]).unwrap();
+        assert!(selection.check_known(tests.iter(), "list").is_ok());
+    }
+
+    #[test]
+    fn filter_tags_and_duration() {
+        let short = test(
+            "1.1",
+            "1:A",
+            "LOW",
+            r#"tags = ["Smoke"]
+estimated_duration = "5m""#,
+        );
+        let mut long = test("1.2", "1:A", "LOW", r#"tags = ["long"]"#);
+        long.instructions = vec!["wait_s 7200".to_string()];
+        let tags = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let filter = TestFilter::new(&tags(&["smoke"]), &[], None).unwrap();
+        assert!(filter.matches(&short).unwrap());
+        assert!(!filter.matches(&long).unwrap());
+        let filter = TestFilter::new(&[], &tags(&["LONG"]), None).unwrap();
+        assert!(filter.matches(&short).unwrap() && !filter.matches(&long).unwrap());
+        let filter = TestFilter::new(&[], &[], Some("1h")).unwrap();
+        assert!(filter.matches(&short).unwrap() && !filter.matches(&long).unwrap());
+        assert!(TestFilter::new(&[], &[], Some("soon")).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+pub const INCLUDE_KEYWORD: &str = "include";
+
+// Guards against snippets including each other in a loop.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Named list of instructions that tests can pull in with
+/// `include <name> [param=value...]`. `{param}` placeholders in the
+/// instructions are replaced by the include arguments or by `params` defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Snippet {
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    pub instructions: Vec<String>,
+}
+
+pub type Snippets = BTreeMap<String, Snippet>;
+
+/// Replace every `include` line with the instructions of the named snippet.
+/// Placeholders named in `passthrough` are left in place for a later pass,
+/// ex: the matrix keys of the test including the snippet.
+pub fn expand_snippets(
+    lines: &[String],
+    snippets: &Snippets,
+    passthrough: &[&String],
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let kept: BTreeMap<String, String> = passthrough
+        .iter()
+        .map(|key| (key.to_string(), format!("{{{}}}", key)))
+        .collect();
+    expand_with_depth(lines, snippets, &kept, 0)
+}
+
+fn expand_with_depth(
+    lines: &[String],
+    snippets: &Snippets,
+    kept: &BTreeMap<String, String>,
+    depth: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut expanded = Vec::with_capacity(lines.len());
+
+    for line in lines {
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some(INCLUDE_KEYWORD) {
+            expanded.push(line.clone());
+            continue;
+        }
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(format!("Snippet includes nested too deeply at '{}'", line.trim()).into());
+        }
+
+        let name = fields
+            .next()
+            .ok_or_else(|| format!("Missing snippet name in '{}'", line.trim()))?;
+        let snippet = snippets
+            .get(name)
+            .ok_or_else(|| format!("Unknown snippet '{}' in '{}'", name, line.trim()))?;
+
+        let mut values = kept.clone();
+        values.extend(snippet.params.clone());
+        for arg in fields {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid snippet argument '{}', expected key=value", arg))?;
+            let placeholder = format!("{{{}}}", key);
+            if !snippet
+                .instructions
+                .iter()
+                .any(|l| l.contains(&placeholder))
+            {
+                return Err(format!("Snippet '{}' has no parameter '{}'", name, key).into());
+            }
+            values.insert(key.to_string(), value.to_string());
+        }
+
+        let substituted = snippet
+            .instructions
+            .iter()
+            .map(|l| substitute_params(l, &values, name))
+            .collect::<Result<Vec<_>, _>>()?;
+        expanded.extend(expand_with_depth(&substituted, snippets, kept, depth + 1)?);
+    }
+
+    Ok(expanded)
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace every `{key}` in `line` by its value. Only braces around an
+/// identifier are placeholders, other braces, ex: a JSON argument, are kept.
+/// `source` names the snippet or test the placeholders belong to, for error
+/// messages.
+pub fn substitute_params(
+    line: &str,
+    values: &BTreeMap<String, String>,
+    source: &str,
+) -> Result<String, Box<dyn Error>> {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let key = after.find('}').map(|end| &after[..end]);
+        match key {
+            Some(key) if is_placeholder_name(key) => {
+                let value = values
+                    .get(key)
+                    .ok_or_else(|| format!("Missing parameter '{}' for '{}'", key, source))?;
+                result.push_str(value);
+                rest = &after[key.len() + 1..];
+            }
+            _ => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn snippets() -> Snippets {
+        let mut snippets = Snippets::new();
+        snippets.insert(
+            "set_format".to_string(),
+            Snippet {
+                params: values(&[("force", "--force")]),
+                instructions: lines(&["ccc set pixel_format={pixel_format} {force}"]),
+            },
+        );
+        snippets.insert(
+            "stdv".to_string(),
+            Snippet {
+                params: BTreeMap::new(),
+                instructions: lines(&["## STDV ##", "include set_format pixel_format=0"]),
+            },
+        );
+        snippets.insert(
+            "loop".to_string(),
+            Snippet {
+                params: BTreeMap::new(),
+                instructions: lines(&["include loop"]),
+            },
+        );
+        snippets
+    }
+
+    #[test]
+    fn substitutes_identifier_placeholders() {
+        let values = values(&[("pixel_format", "2"), ("_n1", "x")]);
+        assert_eq!(
+            substitute_params("ccc set pixel_format={pixel_format} {_n1}", &values, "t").unwrap(),
+            "ccc set pixel_format=2 x"
+        );
+        let err = substitute_params("wait_s {delay}", &values, "t").unwrap_err();
+        assert!(err.to_string().contains("Missing parameter 'delay'"));
+    }
+
+    #[test]
+    fn keeps_literal_braces() {
+        let values = values(&[("mode", "fast")]);
+        for line in [
+            r#"panorama run --args {"mode": "slow"}"#,
+            "echo { unclosed",
+            "echo {} {1x} { mode }",
+            "echo }{",
+        ] {
+            assert_eq!(substitute_params(line, &values, "t").unwrap(), line);
+        }
+        assert_eq!(
+            substitute_params(r#"panorama {"mode": "{mode}"}"#, &values, "t").unwrap(),
+            r#"panorama {"mode": "fast"}"#
+        );
+    }
+
+    #[test]
+    fn expands_nested_includes_with_defaults() {
+        let expanded = expand_snippets(
+            &lines(&[
+                "wait_s 1",
+                "include stdv",
+                "include set_format pixel_format=3 force=",
+            ]),
+            &snippets(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            expanded,
+            lines(&[
+                "wait_s 1",
+                "## STDV ##",
+                "ccc set pixel_format=0 --force",
+                "ccc set pixel_format=3 ",
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_passthrough_placeholders() {
+        let key = "pixel_format".to_string();
+        let expanded = expand_snippets(
+            &lines(&["include set_format pixel_format={pixel_format}"]),
+            &snippets(),
+            &[&key],
+        )
+        .unwrap();
+        assert_eq!(
+            expanded,
+            lines(&["ccc set pixel_format={pixel_format} --force"])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_includes() {
+        let snippets = snippets();
+        for (line, message) in [
+            ("include", "Missing snippet name"),
+            ("include nope", "Unknown snippet 'nope'"),
+            ("include set_format colour=red", "has no parameter 'colour'"),
+            ("include set_format pixel_format", "expected key=value"),
+            ("include set_format", "Missing parameter 'pixel_format'"),
+            ("include loop", "nested too deeply"),
+        ] {
+            let err = expand_snippets(&lines(&[line]), &snippets, &[]).unwrap_err();
+            assert!(err.to_string().contains(message), "{}: {}", line, err);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -11,6 +11,51 @@ pub struct Cli {
     pub command: Command,
 }
 
+/// Tag and duration filters shared by the commands selecting tests.
+#[derive(Args, Debug)]
+pub struct TestFilterArgs {
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        help = "Keep tests with this tag, can be repeated"
+    )]
+    pub tags: Vec<String>,
+    #[arg(
+        long = "exclude-tag",
+        value_name = "TAG",
+        help = "Drop tests with this tag, can be repeated"
+    )]
+    pub exclude_tags: Vec<String>,
+    #[arg(
+        long = "max-duration",
+        value_name = "DURATION",
+        help = "Drop tests estimated to run longer than this, ex: 30m"
+    )]
+    pub max_duration: Option<String>,
+}
+
+/// Options running the selected tests again, ex: for reliability soak tests.
+#[derive(Args, Debug)]
+pub struct RepeatArgs {
+    #[arg(
+        long = "repeat",
+        value_name = "N",
+        help = "Run the selected tests N times"
+    )]
+    pub repeat: Option<u32>,
+    #[arg(
+        long = "for",
+        value_name = "DURATION",
+        help = "Run the selected tests again until DURATION has passed, ex: 8h"
+    )]
+    pub for_duration: Option<String>,
+    #[arg(
+        long = "stop-on-fail",
+        help = "Stop repeating at the first failed test"
+    )]
+    pub stop_on_fail: bool,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Generate email report
@@ -44,6 +89,26 @@ Run specific test instructions.
     vtg test 1.1
     vtg test 1.1 1.2 1.3
 
+- Option to select tests by ID prefix, ID range, group or priority.
+Selectors starting with ! exclude tests.
+    vtg test 1.*
+    vtg test 2.1-2.6 !2.4
+    vtg test group:NETWORKING
+    vtg test priority:HIGH !6.*
+
+- Option to filter the selected tests by tag or estimated duration
+    vtg test --tag smoke --exclude-tag long --max-duration 30m
+
+- Option to run the selected tests again, a number of times or for a duration,
+optionally stopping at the first failed test
+    vtg test 1.4 --repeat 50
+    vtg test 1.4 --for 8h --stop-on-fail
+
+- Option to pin the firmware image under test for the whole campaign. Its
+file name and SHA-256 go into the report, and flash_firmware flashes it when
+no image is given.
+    vtg test 4.* --firmware Path/To/ultra.cepbin
+
 - Option to specify the input test file.
 No specified input file will use the default instruction file name in the same
 directory as the executable.
@@ -62,6 +127,28 @@ directory as the executable.
         input_instruction_file: Option<String>,
         #[arg(short = 'x', long = "input-excel-file")]
         input_excel_file: Option<String>,
+        #[arg(long = "firmware", value_name = "FILE")]
+        firmware: Option<String>,
+        #[command(flatten)]
+        filter: TestFilterArgs,
+        #[command(flatten)]
+        repeat: RepeatArgs,
+    },
+    #[command(
+        about = "Check a test list or instruction file",
+        long_about = r#"
+Check a test list or instruction file, ex: that the profiles applied by
+apply_profile exist in the profiles/ directory and are valid.
+
+- No specified input file will check the default base file in the same
+directory as the executable.
+    vtg lint
+    vtg lint -i Path/To/Instruction_File
+"#
+    )]
+    Lint {
+        #[arg(short = 'i', long = "input-file")]
+        input_file: Option<String>,
     },
     /// Generate Excel report from grouped CSV
     Excel {
@@ -77,11 +164,16 @@ Group tests by label and IDs.
     vtg id-groups GROUP_LABEL_A:1.1,1.2,1.3
     - Multi
     vtg id-groups GROUP_LABEL_A:1.1,1.2,1.3 GROUP_LABEL_B:5.1,6.2,7.3
+    - Selectors
+    vtg id-groups SMOKE:1.*,2.1-2.6,!1.5.* NETWORK:group:NETWORKING
 
 - Option to specify a priority
 Will filter the Ids entered for the priority specified
     vtg id-groups GROUP_LABEL_A:1.1,1.2,1.3 GROUP_LABEL_B:5.1,6.2,7.3 -p HIGH
 
+- Option to filter by tag or estimated duration
+    vtg id-groups SMOKE:1.*,2.* --tag smoke --exclude-tag long --max-duration 30m
+
 - Option to specify the input test file.
 No specified input file will use the default base file name in the same
 directory as the executable.
@@ -102,6 +194,8 @@ directory as the executable. The name should not have the file type in it.
         input_instruction_file: Option<String>,
         #[arg(short = 'o', long = "output-name")]
         output_name: Option<String>,
+        #[command(flatten)]
+        filter: TestFilterArgs,
     },
     /// Group tests by priority only
     Priority {
@@ -111,5 +205,7 @@ directory as the executable. The name should not have the file type in it.
         input_instruction_file: Option<String>,
         #[arg(short = 'o', long = "output-name")]
         output_name: Option<String>,
+        #[command(flatten)]
+        filter: TestFilterArgs,
     },
 }
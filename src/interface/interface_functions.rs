@@ -1,22 +1,54 @@
 use std::error::Error;
-use std::{ffi::OsStr, path::Path};
+use std::time::Duration;
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
+use crate::files_ops::campaign_ops::Campaign;
 use crate::files_ops::email_ops::generate_email_using_python;
 use crate::files_ops::excel_ops::{convert_csv_to_excel, format_excel_sheet};
 use crate::files_ops::file_ops::{
-    export_grouped_csv, export_grouped_toml, extract_test_ids, test_file_filter,
+    Test, export_grouped_csv, export_grouped_toml, read_group_hooks, test_file_filter,
 };
+use crate::files_ops::instruction_file::InstructionFile;
+use crate::files_ops::lint_ops::lint_file;
+use crate::files_ops::selector_ops::{TestFilter, TestSelection};
+use crate::interface::interface_cli::{RepeatArgs, TestFilterArgs};
 use crate::sanity::sanity_files::{prepend_hash_to_toml, sanity_check_toml};
+use crate::supporters::log_ops::copy_logs;
 use crate::supporters::misc::{
-    generate_email_attachments, get_key_entry_y, print_thick_separator, test_pass_fail_prompt,
+    EMAIL_ATTACHMENTS_FLDR, generate_email_attachments, get_key_entry_y, human_readable_time,
+    parse_duration, print_thick_separator,
+};
+use crate::test_ops::test_ops_process_tests::ar_print_test_item;
+use crate::test_ops::test_ops_run::{
+    RepeatOptions, TestRunMode, run_test_ids, run_test_ids_repeated,
 };
-use crate::test_ops::test_ops_process_tests::{ar_print_test_item, ar_process_test_item};
 
 const DEFAULT_INSTRUCTION_FILE: &str = "validation_test_instructions.toml";
 const DEFAULT_CSV_FILE: &str = "validation_test_report.csv";
-const DEFAULT_EXCEL_FILE: &str = "validation_test_report.xlsx";
 const DEFAULT_BASE_TOML: &str = "base_tests_list.toml";
 
+fn test_filter_from_args(args: &TestFilterArgs) -> Result<TestFilter, Box<dyn Error>> {
+    TestFilter::new(&args.tags, &args.exclude_tags, args.max_duration.as_deref())
+}
+
+fn repeat_options_from_args(args: &RepeatArgs) -> Result<RepeatOptions, Box<dyn Error>> {
+    if args.repeat == Some(0) {
+        return Err("--repeat must be at least 1".into());
+    }
+    Ok(RepeatOptions {
+        iterations: args.repeat,
+        duration: args
+            .for_duration
+            .as_deref()
+            .map(parse_duration)
+            .transpose()?,
+        stop_on_fail: args.stop_on_fail,
+    })
+}
+
 pub fn email_gen(
     email_name: &Option<String>,
     input_excel_file: &String,
@@ -24,10 +56,17 @@ pub fn email_gen(
     recipient_email: &String,
     bypass_generation: bool,
 ) -> Result<(), Box<dyn Error>> {
+    let campaign = Campaign::containing(input_excel_file)?;
     if !bypass_generation {
         println!("Generating email attachments...");
-        generate_email_attachments()?;
+        generate_email_attachments(&campaign.config_dumps_dir())?;
     }
+    let log_count = copy_logs(&campaign.logs_dir(), EMAIL_ATTACHMENTS_FLDR)?;
+    println!(
+        "Attached {} test log(s) from {}",
+        log_count,
+        campaign.logs_dir().display()
+    );
 
     let final_email_name = email_name.as_deref().map(str::to_owned).unwrap_or_else(|| {
         Path::new(input_excel_file)
@@ -48,19 +87,41 @@ pub fn test_run(
     test_ids: Option<Vec<String>>,
     input_instruction_file: &Option<String>,
     input_excel_file: &Option<String>,
+    firmware: &Option<String>,
+    filter_args: &TestFilterArgs,
+    repeat_args: &RepeatArgs,
 ) -> Result<(), Box<dyn Error>> {
+    let test_filter = test_filter_from_args(filter_args)?;
+    let repeat_options = repeat_options_from_args(repeat_args)?;
+
     // Determine if the file is custom
     let is_file_custom = input_instruction_file.is_some();
-    let is_excel_provided = input_excel_file.is_some();
     let is_ids_provided = test_ids.is_some();
 
-    // Resolve file path
-    let file_path: &str = input_instruction_file
-        .as_deref()
-        .unwrap_or(DEFAULT_INSTRUCTION_FILE);
-
-    // Resolve file path
-    let excel_path: &str = input_excel_file.as_deref().unwrap_or(DEFAULT_EXCEL_FILE);
+    // Resolve the campaign: the one holding the given instruction file, else
+    // the latest campaign, else the default instruction file in the working
+    // directory.
+    let mut campaign = match input_instruction_file {
+        Some(path) => Campaign::containing(path)?,
+        None => match Campaign::latest()? {
+            Some(latest) => latest,
+            None => Campaign::containing(DEFAULT_INSTRUCTION_FILE)?,
+        },
+    };
+    let file_path_buf: PathBuf = match input_instruction_file {
+        Some(path) => PathBuf::from(path),
+        None => campaign.instruction_path(),
+    };
+    let file_path: &str = file_path_buf
+        .to_str()
+        .ok_or("Invalid instruction file path")?;
+
+    // Resolve the report, the campaign report if none is given
+    let excel_path_buf: Option<PathBuf> = match input_excel_file {
+        Some(path) => Some(PathBuf::from(path)),
+        None => campaign.report_path().filter(|path| path.exists()),
+    };
+    let excel_path: Option<&str> = excel_path_buf.as_deref().and_then(Path::to_str);
 
     // Check if instruction file exists
     if !Path::new(file_path).exists() {
@@ -68,8 +129,30 @@ pub fn test_run(
     }
 
     // Check if Excel file exists if provided
-    if is_excel_provided && !Path::new(excel_path).exists() {
-        return Err(format!("Excel file not found: {}", excel_path).into());
+    if let Some(excel) = excel_path
+        && !Path::new(excel).exists()
+    {
+        return Err(format!("Excel file not found: {}", excel).into());
+    }
+
+    // Pin the firmware under test, or check the pinned one is unchanged
+    if let Some(file) = firmware {
+        let image = campaign.pin_firmware(file)?;
+        println!(
+            "Firmware under test: {} (SHA-256 {})",
+            image.file, image.sha256
+        );
+        if let Some(excel) = excel_path
+            && let Err(e) = image.record_in_report(Path::new(excel), None)
+        {
+            print_warn_ln!("Failed to record the firmware in the report: {}", e);
+        }
+    } else if let Some(image) = &campaign.firmware {
+        image.verify()?;
+        println!(
+            "Firmware under test: {} (SHA-256 {})",
+            image.file, image.sha256
+        );
     }
 
     // Skip this sanity check if the input is a custom file.
@@ -79,19 +162,39 @@ pub fn test_run(
         sanity_check_toml(file_path)?;
     }
 
-    // Extract test IDs if none were provided
-    let ids_to_run = match test_ids {
-        Some(ids) => ids,
-        None => extract_test_ids(file_path)?,
-    };
+    // Resolve the selectors to test IDs, all tests if none were provided. An ID
+    // of a matrix test selects all of its variants.
+    let instruction_file = InstructionFile::load(file_path)?;
+    let all_tests: Vec<_> = instruction_file.tests().map(|(_, t)| t).collect();
+    let selection = TestSelection::parse(test_ids.as_deref().unwrap_or_default())?;
+    selection.check_known(all_tests.iter().copied(), file_path)?;
+    let mut ids_to_run: Vec<String> = Vec::new();
+    for test in selection.select(all_tests.iter().copied()) {
+        if test_filter.matches(test)? {
+            ids_to_run.push(test.test_id.clone());
+        }
+    }
 
     if !is_ids_provided {
-        for test_id in ids_to_run.clone() {
-            if let Err(e) = ar_print_test_item(file_path, &test_id) {
-                eprintln!("Error processing test '{}': {}", test_id, e);
+        let mut total_duration = Duration::ZERO;
+        let mut total_manual_steps = 0;
+        for test_id in &ids_to_run {
+            match ar_print_test_item(&instruction_file, test_id) {
+                Ok(Some((duration, manual_steps))) => {
+                    total_duration += duration;
+                    total_manual_steps += manual_steps;
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Error processing test '{}': {}", test_id, e),
             }
         }
         print_thick_separator();
+        println!(
+            "{} tests, estimated duration ~{}, {} manual steps",
+            ids_to_run.len(),
+            human_readable_time(total_duration.as_secs() as u32),
+            total_manual_steps
+        );
         println!("Do you want to run all tests?");
         if get_key_entry_y()? == 0 {
             return Ok(());
@@ -99,19 +202,46 @@ pub fn test_run(
     }
 
     // Process each test ID
-    for test_id in ids_to_run {
-        print_thick_separator();
-        if let Err(e) = ar_process_test_item(file_path, &test_id) {
-            eprintln!("Error processing test '{}': {}", test_id, e);
-        }
-        if is_excel_provided {
-            test_pass_fail_prompt(excel_path, &test_id)?;
-        }
+    println!("Campaign directory: {}", campaign.dir.display());
+    if repeat_options.is_repeated() {
+        run_test_ids_repeated(
+            &campaign,
+            file_path,
+            &ids_to_run,
+            excel_path,
+            &repeat_options,
+        )?;
+    } else {
+        let mode = TestRunMode {
+            prompt_verdict: true,
+            ..TestRunMode::default()
+        };
+        run_test_ids(&campaign, file_path, &ids_to_run, excel_path, mode)?;
     }
 
     Ok(())
 }
 
+/// Print the problems found in a base test list or instruction file, and fail
+/// if there is any.
+pub fn lint(input_file: &Option<String>) -> Result<(), Box<dyn Error>> {
+    let file_path: &str = input_file.as_deref().unwrap_or(DEFAULT_BASE_TOML);
+    if !Path::new(file_path).exists() {
+        return Err(format!("File not found: {}", file_path).into());
+    }
+
+    let issues = lint_file(file_path)?;
+    if issues.is_empty() {
+        println!("No problems found in '{}'", file_path);
+        return Ok(());
+    }
+    for issue in &issues {
+        println!("{}: {}", issue.location, issue.message);
+        println!("    {}", issue.line);
+    }
+    Err(format!("{} problem(s) found in '{}'", issues.len(), file_path).into())
+}
+
 pub fn excel_gen(input_instruction_file: &Option<String>) -> Result<(), Box<dyn Error>> {
     // Extract &str from Option<String>
     let file_path: &str = input_instruction_file
@@ -125,25 +255,81 @@ pub fn excel_gen(input_instruction_file: &Option<String>) -> Result<(), Box<dyn
 
     sanity_check_toml(file_path)?; // Now passes &str
 
+    // The report is written next to the instruction file, ex: in its campaign
     let csv_file_name = Path::new(file_path)
         .file_stem()
         .and_then(OsStr::to_str)
         .map(|stem| format!("{}_report.csv", stem))
         .unwrap_or_else(|| "report.csv".into());
+    let csv_path_buf = Path::new(file_path).with_file_name(csv_file_name);
 
     // Perform the excel generation.
-    let csv_path = export_grouped_csv(file_path, &csv_file_name)?;
+    let csv_path = export_grouped_csv(Path::new(file_path), csv_path_buf.as_path())?;
     let xlsx_path = convert_csv_to_excel(&csv_path)?;
     format_excel_sheet(&xlsx_path)?;
     Ok(())
 }
 
+/// Create a campaign directory holding the grouped instruction file and its
+/// report, ex: `campaigns/my_report_example_20250101_120000/`.
+fn generate_campaign(
+    grouped_tests: &[(String, Vec<Test>)],
+    base_path: &str,
+    output_name: &Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    // Generate output file names from Option<String>
+    let output_toml_owned: String = match output_name {
+        Some(name) => format!("{}.toml", name),
+        None => DEFAULT_INSTRUCTION_FILE.to_string(),
+    };
+    let output_csv_owned: String = match output_name {
+        Some(name) => format!("{}.csv", name),
+        None => DEFAULT_CSV_FILE.to_string(),
+    };
+    let output_xlsx_owned: String = Path::new(&output_csv_owned)
+        .with_extension("xlsx")
+        .to_string_lossy()
+        .into_owned();
+
+    let campaign_name = Path::new(&output_toml_owned)
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .unwrap_or("campaign")
+        .to_string();
+    let campaign = Campaign::create(&campaign_name, &output_toml_owned, &output_xlsx_owned)?;
+
+    let output_toml_path = campaign.instruction_path();
+    let output_toml_file: &str = output_toml_path
+        .to_str()
+        .ok_or("Invalid campaign directory path")?;
+    let output_csv_path = campaign.dir.join(&output_csv_owned);
+    let output_csv_file: &str = output_csv_path
+        .to_str()
+        .ok_or("Invalid campaign directory path")?;
+
+    // Export a grouped TOML summary
+    let group_hooks = read_group_hooks(base_path)?;
+    export_grouped_toml(grouped_tests, &group_hooks, output_toml_file)?;
+    prepend_hash_to_toml(output_toml_file)?;
+    sanity_check_toml(output_toml_file)?;
+
+    // CSV → Excel pipeline
+    let csv_path = export_grouped_csv(output_toml_file, output_csv_file)?;
+    let xlsx_path = convert_csv_to_excel(&csv_path)?;
+    format_excel_sheet(&xlsx_path)?;
+
+    Ok(())
+}
+
 pub fn group_tests_id(
     groups: &Vec<String>,
     priority: &Option<String>,
     input_base: &Option<String>,
     output_name: &Option<String>,
+    filter_args: &TestFilterArgs,
 ) -> Result<(), Box<dyn Error>> {
+    let test_filter = test_filter_from_args(filter_args)?;
+
     // Extract &str from Option<String>
     let file_path: &str = input_base.as_deref().unwrap_or(DEFAULT_BASE_TOML);
 
@@ -168,41 +354,21 @@ pub fn group_tests_id(
     // Apply filter
     let mut grouped_tests = Vec::new();
     for (label, ids) in &label_groups {
-        let filtered = test_file_filter(file_path, ids, priority)?;
+        let filtered = test_file_filter(file_path, ids, priority, &test_filter)?;
         grouped_tests.push((label.clone(), filtered));
     }
 
-    // Generate output file names from Option<String>
-    let output_toml_owned: String = match output_name {
-        Some(name) => format!("{}.toml", name),
-        None => DEFAULT_INSTRUCTION_FILE.to_string(),
-    };
-    let output_csv_owned: String = match output_name {
-        Some(name) => format!("{}.csv", name),
-        None => DEFAULT_CSV_FILE.to_string(),
-    };
-
-    let output_toml_file: &String = &output_toml_owned;
-    let output_csv_file: &String = &output_csv_owned;
-
-    // Export a grouped TOML summary
-    export_grouped_toml(&grouped_tests, output_toml_file)?;
-    prepend_hash_to_toml(output_toml_file)?;
-    sanity_check_toml(output_toml_file)?;
-
-    // CSV → Excel pipeline
-    let csv_path = export_grouped_csv(output_toml_file, output_csv_file)?;
-    let xlsx_path = convert_csv_to_excel(&csv_path)?;
-    format_excel_sheet(&xlsx_path)?;
-
-    Ok(())
+    generate_campaign(&grouped_tests, file_path, output_name)
 }
 
 pub fn group_tests_priority(
     priority: &String,
     input_base: &Option<String>,
     output_name: &Option<String>,
+    filter_args: &TestFilterArgs,
 ) -> Result<(), Box<dyn Error>> {
+    let test_filter = test_filter_from_args(filter_args)?;
+
     // Extract &str from Option<String>
     let file_path: &str = input_base.as_deref().unwrap_or(DEFAULT_BASE_TOML);
 
@@ -222,32 +388,9 @@ pub fn group_tests_priority(
     // Apply filter
     let mut grouped_tests = Vec::new();
     for (label, ids) in &label_groups {
-        let filtered = test_file_filter(file_path, ids, &priority_opt)?;
+        let filtered = test_file_filter(file_path, ids, &priority_opt, &test_filter)?;
         grouped_tests.push((label.clone(), filtered));
     }
 
-    // Generate output file names from Option<String>
-    let output_toml_owned: String = match output_name {
-        Some(name) => format!("{}.toml", name),
-        None => DEFAULT_INSTRUCTION_FILE.to_string(),
-    };
-    let output_csv_owned: String = match output_name {
-        Some(name) => format!("{}.csv", name),
-        None => DEFAULT_CSV_FILE.to_string(),
-    };
-
-    let output_toml_file: &String = &output_toml_owned;
-    let output_csv_file: &String = &output_csv_owned;
-
-    // Export a grouped TOML summary
-    export_grouped_toml(&grouped_tests, output_toml_file)?;
-    prepend_hash_to_toml(output_toml_file)?;
-    sanity_check_toml(output_toml_file)?;
-
-    // CSV → Excel pipeline
-    let csv_path = export_grouped_csv(output_toml_file, output_csv_file)?;
-    let xlsx_path = convert_csv_to_excel(&csv_path)?;
-    format_excel_sheet(&xlsx_path)?;
-
-    Ok(())
+    generate_campaign(&grouped_tests, file_path, output_name)
 }
@@ -3,8 +3,6 @@ use clap::{CommandFactory, Parser};
 // macro_use modules must be declared before modules that use it.
 #[macro_use]
 mod macros;
-#[macro_use(bytes_u32)]
-extern crate dhcp4r;
 
 mod commands;
 mod files_ops;
@@ -15,7 +13,7 @@ mod test_ops;
 
 use crate::interface::interface_cli::{Cli, Command};
 use crate::interface::interface_functions::{
-    email_gen, excel_gen, group_tests_id, group_tests_priority, test_run,
+    email_gen, excel_gen, group_tests_id, group_tests_priority, lint, test_run,
 };
 use crate::sanity::sanity_env::sanity_dependencies;
 use crate::sanity::sanity_files::sanity_check_python_scripts;
@@ -56,8 +54,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             test_ids,
             input_instruction_file,
             input_excel_file,
+            firmware,
+            filter,
+            repeat,
         } => {
-            test_run(test_ids, &input_instruction_file, &input_excel_file)?;
+            test_run(
+                test_ids,
+                &input_instruction_file,
+                &input_excel_file,
+                &firmware,
+                &filter,
+                &repeat,
+            )?;
+        }
+        Command::Lint { input_file } => {
+            lint(&input_file)?;
         }
         Command::Excel {
             input_instruction_file,
@@ -69,15 +80,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             priority,
             input_instruction_file,
             output_name,
+            filter,
         } => {
-            group_tests_id(&groups, &priority, &input_instruction_file, &output_name)?;
+            group_tests_id(
+                &groups,
+                &priority,
+                &input_instruction_file,
+                &output_name,
+                &filter,
+            )?;
         }
         Command::Priority {
             priority,
             input_instruction_file,
             output_name,
+            filter,
         } => {
-            group_tests_priority(&priority, &input_instruction_file, &output_name)?;
+            group_tests_priority(&priority, &input_instruction_file, &output_name, &filter)?;
         }
     }
 
@@ -22,13 +22,13 @@ struct ScriptCheck<'a> {
 const SCRIPT_CHECKS: &[ScriptCheck] = &[
     ScriptCheck {
         path: "resources/excel_format.py",
-        expected_version: "1.1.5",
-        expected_hash: 0x5FBF2128, // Replace with actual hash
+        expected_version: "1.1.10",
+        expected_hash: 0xE80859DC, // Replace with actual hash
     },
     ScriptCheck {
         path: "resources/excel_to_email_template.py",
-        expected_version: "1.1.6",
-        expected_hash: 0xA06FD7C9, // Replace with actual hash
+        expected_version: "1.1.9",
+        expected_hash: 0x17303464, // Replace with actual hash
     },
 ];
 
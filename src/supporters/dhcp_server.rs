@@ -1,12 +1,24 @@
+use chrono::Local;
 use dhcp4r::{options, packet, server};
+use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
 use std::error::Error;
-use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 use std::ops::Add;
-use std::thread;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
-// Server configuration
+// Optional server configuration file, looked up in the working directory.
+const DHCP_CONFIG_FILE: &str = "dhcp_config.toml";
+
+// Default server configuration
 const SERVER_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 32, 100);
 const IP_START: [u8; 4] = [192, 168, 32, 40]; // IP pool starting IP
 const SUBNET_MASK: Ipv4Addr = Ipv4Addr::new(255, 255, 255, 0);
@@ -18,28 +30,376 @@ const DNS_IPS: [Ipv4Addr; 2] = [
 const ROUTER_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 32, 100);
 const LEASE_DURATION_SECS: u32 = 7200;
 const LEASE_NUM: u32 = 10; // IP pool size
+#[cfg(windows)]
 const NIC_NAME: &str = "Ethernet"; // NIC where sensor is connected
+#[cfg(not(windows))]
+const NIC_NAME: &str = "eth0"; // NIC where sensor is connected
+
+// How often an idle server thread checks whether it has been asked to stop.
+const SERVER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Largest DHCP message received or sent, as in dhcp4r.
+const DHCP_BUFFER_SIZE: usize = 1500;
+
+/// DHCP server settings. Every field falls back to the built-in default when it
+/// is missing from `dhcp_config.toml` or from the `dhcp_server` instruction.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DhcpConfig {
+    pub nic_name: String,
+    pub server_ip: Ipv4Addr,
+    pub pool_start: Ipv4Addr,
+    pub pool_size: u32,
+    pub subnet_mask: Ipv4Addr,
+    pub router: Ipv4Addr,
+    pub dns: Vec<Ipv4Addr>,
+    pub lease_secs: u32,
+    pub reservations: Vec<Reservation>,
+}
+
+/// Static MAC to IP assignment. The IP does not need to be inside the pool.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reservation {
+    #[serde(deserialize_with = "deserialize_mac")]
+    pub mac: [u8; 6],
+    pub ip: Ipv4Addr,
+}
+
+fn deserialize_mac<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 6], D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_mac(&value).map_err(serde::de::Error::custom)
+}
+
+impl Default for DhcpConfig {
+    fn default() -> Self {
+        DhcpConfig {
+            nic_name: NIC_NAME.to_string(),
+            server_ip: SERVER_IP,
+            pool_start: Ipv4Addr::from(IP_START),
+            pool_size: LEASE_NUM,
+            subnet_mask: SUBNET_MASK,
+            router: ROUTER_IP,
+            dns: DNS_IPS.to_vec(),
+            lease_secs: LEASE_DURATION_SECS,
+            reservations: Vec::new(),
+        }
+    }
+}
+
+impl DhcpConfig {
+    /// Load `dhcp_config.toml` if present, otherwise use the defaults.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        if !Path::new(DHCP_CONFIG_FILE).exists() {
+            return Ok(DhcpConfig::default());
+        }
+        let content = fs::read_to_string(DHCP_CONFIG_FILE)?;
+        let config: DhcpConfig = toml::from_str(&content)
+            .map_err(|e| format!("Invalid DHCP config '{}': {}", DHCP_CONFIG_FILE, e))?;
+        config
+            .validate()
+            .map_err(|e| format!("Invalid DHCP config '{}': {}", DHCP_CONFIG_FILE, e))?;
+        Ok(config)
+    }
+
+    /// Apply `key=value` overrides from an instruction line, e.g.
+    /// `dhcp_server pool=192.168.32.40-50 lease=60 dns=8.8.8.8,4.4.4.4`.
+    pub fn apply_args(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        for arg in args {
+            let (key, value) = arg
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid dhcp_server argument '{}'", arg))?;
+            match key {
+                "nic" => self.nic_name = value.to_string(),
+                "server_ip" => self.server_ip = parse_ipv4(value)?,
+                "pool" => {
+                    let (start, size) = parse_pool(value)?;
+                    self.pool_start = start;
+                    self.pool_size = size;
+                }
+                "mask" => self.subnet_mask = parse_ipv4(value)?,
+                "router" => self.router = parse_ipv4(value)?,
+                "dns" => {
+                    self.dns = value
+                        .split(',')
+                        .filter(|s| !s.is_empty())
+                        .map(parse_ipv4)
+                        .collect::<Result<_, _>>()?;
+                }
+                "reserve" => {
+                    let (mac, ip) = value.split_once('@').ok_or_else(|| {
+                        format!("Invalid reservation '{}', expected <mac>@<ip>", value)
+                    })?;
+                    self.reservations.push(Reservation {
+                        mac: parse_mac(mac)?,
+                        ip: parse_ipv4(ip)?,
+                    });
+                }
+                "lease" => {
+                    self.lease_secs = value
+                        .parse()
+                        .map_err(|e| format!("Invalid lease '{}': {}", value, e))?;
+                }
+                _ => return Err(format!("Unknown dhcp_server argument '{}'", key).into()),
+            }
+        }
+        self.validate()
+    }
+
+    /// Fail on an empty pool, or one running past 255.255.255.255.
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.pool_size == 0 {
+            return Err("The DHCP pool is empty, pool_size must be at least 1".into());
+        }
+        if self
+            .pool_start_num()
+            .checked_add(self.pool_size - 1)
+            .is_none()
+        {
+            return Err(format!(
+                "The DHCP pool of {} addresses from {} runs past 255.255.255.255",
+                self.pool_size, self.pool_start
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn pool_start_num(&self) -> u32 {
+        self.pool_start.into()
+    }
+
+    fn reserved_ip(&self, mac: &[u8; 6]) -> Option<Ipv4Addr> {
+        self.reservations
+            .iter()
+            .find(|r| &r.mac == mac)
+            .map(|r| r.ip)
+    }
 
-// Derived constants
-const IP_START_NUM: u32 = bytes_u32!(IP_START);
+    fn reserved_mac(&self, ip: &Ipv4Addr) -> Option<[u8; 6]> {
+        self.reservations
+            .iter()
+            .find(|r| &r.ip == ip)
+            .map(|r| r.mac)
+    }
+}
+
+fn parse_ipv4(value: &str) -> Result<Ipv4Addr, Box<dyn Error>> {
+    value
+        .parse()
+        .map_err(|e| format!("Invalid IPv4 address '{}': {}", value, e).into())
+}
 
-pub fn dhcp_server_runner() -> Result<(), Box<dyn Error>> {
-    if let Some(ip) = get_ipv4_address(NIC_NAME)? {
-        // If an IP address for the network interface can be found, Run server in another thread
-        thread::spawn(|| start_dhcp_server(ip));
+/// Parse a pool range written as `192.168.32.40-50` or
+/// `192.168.32.40-192.168.32.50`. Returns the start address and pool size.
+fn parse_pool(value: &str) -> Result<(Ipv4Addr, u32), Box<dyn Error>> {
+    let (start, end) = value
+        .split_once('-')
+        .ok_or_else(|| format!("Invalid pool '{}', expected <start_ip>-<end>", value))?;
+    let start = parse_ipv4(start)?;
+    let end = if end.contains('.') {
+        parse_ipv4(end)?
     } else {
-        return Err(format!(
-            "Network interface '{}' has no active IPv4 address or was not found.",
-            NIC_NAME
-        )
-        .into());
+        let last: u8 = end
+            .parse()
+            .map_err(|e| format!("Invalid pool end '{}': {}", end, e))?;
+        let o = start.octets();
+        Ipv4Addr::new(o[0], o[1], o[2], last)
+    };
+
+    let (start_num, end_num): (u32, u32) = (start.into(), end.into());
+    if end_num < start_num {
+        return Err(format!("Invalid pool '{}': end is before start", value).into());
     }
+    Ok((start, end_num - start_num + 1))
+}
 
-    Ok(())
+/// Current lease as seen by the running server.
+#[derive(Debug, Clone)]
+pub struct LeaseInfo {
+    pub mac: [u8; 6],
+    pub ip: Ipv4Addr,
+    pub expires_in: Duration,
+}
+
+/// Lease bookkeeping shared between the server thread and the handle.
+#[derive(Default)]
+struct LeaseState {
+    leases: HashMap<Ipv4Addr, ([u8; 6], Instant)>,
+    last_lease: u32,
+    event_log: Option<csv::Writer<File>>,
+}
+
+/// Handle on a running DHCP server. The server thread is stopped when the
+/// handle is stopped or dropped.
+pub struct DhcpServerHandle {
+    config: Arc<DhcpConfig>,
+    state: Arc<Mutex<LeaseState>>,
+    stop_flag: Arc<AtomicBool>,
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl DhcpServerHandle {
+    /// Bind the DHCP socket and start serving in a background thread.
+    /// Bind failures are returned to the caller instead of panicking the thread.
+    /// Every DHCP message seen or sent is appended to `event_log_path` as CSV.
+    pub fn start(
+        config: DhcpConfig,
+        event_log_path: Option<PathBuf>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let ip = get_ipv4_address(&config.nic_name)?.ok_or_else(|| {
+            format!(
+                "Network interface '{}' has no active IPv4 address or was not found.",
+                config.nic_name
+            )
+        })?;
+
+        // Linux only delivers broadcast DISCOVERs to sockets bound to the wildcard
+        // address, so the interface IP is only used for the bind on Windows.
+        let bind_ip = if cfg!(windows) {
+            ip
+        } else {
+            "0.0.0.0".to_string()
+        };
+        let socket_str = format!("{}:67", bind_ip);
+        let socket = UdpSocket::bind(&socket_str)
+            .map_err(|e| format!("Failed to bind DHCP server to {}: {}", socket_str, e))?;
+        socket.set_broadcast(true)?;
+        // Wake up periodically so the stop flag is honoured when idle.
+        socket.set_read_timeout(Some(SERVER_POLL_INTERVAL))?;
+
+        let event_log = match event_log_path {
+            Some(path) => {
+                let wtr = open_event_log(&path)?;
+                println!("[DHCP] Event log: {}", path.display());
+                Some(wtr)
+            }
+            None => None,
+        };
+
+        let config = Arc::new(config);
+        let state = Arc::new(Mutex::new(LeaseState {
+            event_log,
+            ..LeaseState::default()
+        }));
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        let thread_config = Arc::clone(&config);
+        let thread_state = Arc::clone(&state);
+        let thread_flag = Arc::clone(&stop_flag);
+        let thread_handle = thread::spawn(move || {
+            serve_until_stopped(socket, thread_config, thread_state, thread_flag)
+        });
+
+        println!("[DHCP] Server started on {}", socket_str);
+        Ok(DhcpServerHandle {
+            config,
+            state,
+            stop_flag,
+            thread_handle: Some(thread_handle),
+        })
+    }
+
+    pub fn config(&self) -> &DhcpConfig {
+        &self.config
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.thread_handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Snapshot of the leases that have not expired yet.
+    pub fn leases(&self) -> Vec<LeaseInfo> {
+        let now = Instant::now();
+        let state = self.state.lock().unwrap();
+        let mut leases: Vec<LeaseInfo> = state
+            .leases
+            .iter()
+            .filter(|(_, (_, expiry))| *expiry > now)
+            .map(|(ip, (mac, expiry))| LeaseInfo {
+                mac: *mac,
+                ip: *ip,
+                expires_in: *expiry - now,
+            })
+            .collect();
+        leases.sort_by_key(|lease| u32::from(lease.ip));
+        leases
+    }
+
+    /// Poll the lease table until a lease is handed out, to `mac` if given.
+    pub fn wait_for_lease(
+        &self,
+        mac: Option<[u8; 6]>,
+        timeout: Duration,
+    ) -> Result<LeaseInfo, Box<dyn Error>> {
+        let start = Instant::now();
+        loop {
+            if let Some(lease) = self
+                .leases()
+                .into_iter()
+                .find(|lease| mac.is_none_or(|m| m == lease.mac))
+            {
+                return Ok(lease);
+            }
+            if !self.is_running() {
+                return Err("DHCP server stopped while waiting for a lease".into());
+            }
+            if start.elapsed() >= timeout {
+                return Err(match mac {
+                    Some(m) => format!(
+                        "No DHCP lease for {} within {}s",
+                        format_mac(&m),
+                        timeout.as_secs()
+                    ),
+                    None => format!("No DHCP lease handed out within {}s", timeout.as_secs()),
+                }
+                .into());
+            }
+            thread::sleep(SERVER_POLL_INTERVAL);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_flag.store(true, Ordering::Release);
+        if let Some(handle) = self.thread_handle.take() {
+            if let Err(e) = handle.join() {
+                print_warn_ln!("Failed to join DHCP server thread: {:?}", e);
+            }
+            println!("[DHCP] Server stopped.");
+        }
+    }
+}
+
+impl Drop for DhcpServerHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Parse a MAC address written as `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`.
+pub fn parse_mac(value: &str) -> Result<[u8; 6], Box<dyn Error>> {
+    let parts: Vec<&str> = value.split([':', '-']).collect();
+    if parts.len() != 6 {
+        return Err(format!("Invalid MAC address '{}'", value).into());
+    }
+    let mut mac = [0u8; 6];
+    for (byte, part) in mac.iter_mut().zip(parts) {
+        *byte = u8::from_str_radix(part, 16)
+            .map_err(|e| format!("Invalid MAC address '{}': {}", value, e))?;
+    }
+    Ok(mac)
 }
 
 /// Get the IPv4 address of a network interface by name, only if it's connected.
 /// Returns `Some(ipv4_string)` or `None` if not found / not connected.
+#[cfg(windows)]
 fn get_ipv4_address(interface_name: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
     let adapters = ipconfig::get_adapters()?;
 
@@ -58,103 +418,260 @@ fn get_ipv4_address(interface_name: &str) -> Result<Option<String>, Box<dyn std:
     Ok(None) // no adapter by that name
 }
 
-fn start_dhcp_server(ip: String) {
-    let socket_str = format!("{}:67", ip);
-    let socket = UdpSocket::bind(socket_str).unwrap();
-    socket.set_broadcast(true).unwrap();
+/// Get the IPv4 address of a network interface by name.
+/// Returns `Some(ipv4_string)` or `None` if not found / no IPv4 assigned.
+#[cfg(not(windows))]
+fn get_ipv4_address(interface_name: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let interfaces = if_addrs::get_if_addrs()?;
 
-    let ms = MyServer {
-        leases: HashMap::new(),
-        last_lease: 0,
-        lease_duration: Duration::new(LEASE_DURATION_SECS as u64, 0),
+    Ok(interfaces
+        .into_iter()
+        .filter(|iface| iface.name == interface_name)
+        .find_map(|iface| match iface.ip() {
+            IpAddr::V4(ipv4) => Some(ipv4.to_string()),
+            IpAddr::V6(_) => None,
+        }))
+}
+
+fn serve_until_stopped(
+    socket: UdpSocket,
+    config: Arc<DhcpConfig>,
+    state: Arc<Mutex<LeaseState>>,
+    stop_flag: Arc<AtomicBool>,
+) {
+    let mut handler = MyServer {
+        state,
+        config: Arc::clone(&config),
     };
-    server::Server::serve(socket, SERVER_IP, ms);
+    let mut buffer = [0u8; DHCP_BUFFER_SIZE];
+    // The flag is checked before every packet, so a busy network does not
+    // keep the server running after `stop`.
+    while !stop_flag.load(Ordering::Acquire) {
+        let (len, src) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            // The read timeout is the expected way out of an idle wait
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                print_warn_ln!("DHCP server stopped on error: {}", e);
+                return;
+            }
+        };
+        if let Ok(in_packet) = packet::Packet::from(&buffer[..len]) {
+            let responder = Responder {
+                socket: &socket,
+                src,
+                server_ip: config.server_ip,
+            };
+            handler.handle_request(&responder, in_packet);
+        }
+    }
+}
+
+/// Replies to the client of the packet being handled, as
+/// `dhcp4r::server::Server` does. The receive loop is ours so it can check the
+/// stop flag between packets.
+struct Responder<'a> {
+    socket: &'a UdpSocket,
+    src: SocketAddr,
+    server_ip: Ipv4Addr,
+}
+
+impl Responder<'_> {
+    /// Whether the packet names this server, as opposed to another one on the
+    /// network.
+    fn for_this_server(&self, in_packet: &packet::Packet) -> bool {
+        matches!(
+            in_packet.option(options::SERVER_IDENTIFIER),
+            Some(options::DhcpOption::ServerIdentifier(ip)) if *ip == self.server_ip
+        )
+    }
+
+    fn reply(
+        &self,
+        msg_type: options::MessageType,
+        additional_options: Vec<options::DhcpOption>,
+        offer_ip: Ipv4Addr,
+        req_packet: packet::Packet,
+    ) -> std::io::Result<usize> {
+        let ciaddr = match msg_type {
+            options::MessageType::Nak => Ipv4Addr::UNSPECIFIED,
+            _ => req_packet.ciaddr,
+        };
+        let mut opts = vec![
+            options::DhcpOption::DhcpMessageType(msg_type),
+            options::DhcpOption::ServerIdentifier(self.server_ip),
+        ];
+        opts.extend(additional_options);
+        if let Some(options::DhcpOption::ParameterRequestList(requested)) =
+            req_packet.option(options::PARAMETER_REQUEST_LIST)
+        {
+            server::filter_options_by_req(&mut opts, requested);
+        }
+
+        let out_packet = packet::Packet {
+            reply: true,
+            hops: 0,
+            xid: req_packet.xid,
+            secs: 0,
+            broadcast: req_packet.broadcast,
+            ciaddr,
+            yiaddr: offer_ip,
+            siaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: req_packet.giaddr,
+            chaddr: req_packet.chaddr,
+            options: opts,
+        };
+        let mut addr = self.src;
+        if out_packet.broadcast || addr.ip() == IpAddr::V4(Ipv4Addr::UNSPECIFIED) {
+            addr.set_ip(IpAddr::V4(Ipv4Addr::BROADCAST));
+        }
+        let mut buffer = [0u8; DHCP_BUFFER_SIZE];
+        self.socket.send_to(out_packet.encode(&mut buffer), addr)
+    }
 }
 
 struct MyServer {
-    leases: HashMap<Ipv4Addr, ([u8; 6], Instant)>,
-    last_lease: u32,
-    lease_duration: Duration,
+    state: Arc<Mutex<LeaseState>>,
+    config: Arc<DhcpConfig>,
 }
 
-impl server::Handler for MyServer {
-    fn handle_request(&mut self, server: &server::Server, in_packet: packet::Packet) {
-        match in_packet.message_type() {
-            Ok(options::MessageType::Discover) => {
+impl MyServer {
+    fn handle_request(&mut self, server: &Responder, in_packet: packet::Packet) {
+        let config = &self.config;
+        let mut state = self.state.lock().unwrap();
+        let message_type = match in_packet.message_type() {
+            Ok(t) => t,
+            Err(_) => return,
+        };
+        let requested_ip = match in_packet.option(options::REQUESTED_IP_ADDRESS) {
+            Some(options::DhcpOption::RequestedIpAddress(x)) => *x,
+            _ => in_packet.ciaddr,
+        };
+        state.log_event(
+            message_type_name(message_type),
+            &in_packet.chaddr,
+            &requested_ip,
+            "",
+        );
+
+        match message_type {
+            options::MessageType::Discover => {
+                // Reserved addresses always win
+                if let Some(ip) = config.reserved_ip(&in_packet.chaddr) {
+                    state.log_event("OFFER", &in_packet.chaddr, &ip, "reserved");
+                    reply(server, config, options::MessageType::Offer, in_packet, &ip);
+                    return;
+                }
                 // Prefer client's choice if available
                 if let Some(options::DhcpOption::RequestedIpAddress(addr)) =
                     in_packet.option(options::REQUESTED_IP_ADDRESS)
                 {
                     let addr = *addr;
-                    if self.available(&in_packet.chaddr, &addr) {
-                        reply(server, options::MessageType::Offer, in_packet, &addr);
+                    if state.available(config, &in_packet.chaddr, &addr) {
+                        state.log_event("OFFER", &in_packet.chaddr, &addr, "requested");
+                        reply(
+                            server,
+                            config,
+                            options::MessageType::Offer,
+                            in_packet,
+                            &addr,
+                        );
                         return;
                     }
                 }
                 // Otherwise prefer existing (including expired if available)
-                if let Some(ip) = self.current_lease(&in_packet.chaddr) {
-                    reply(server, options::MessageType::Offer, in_packet, &ip);
+                if let Some(ip) = state.current_lease(&in_packet.chaddr) {
+                    state.log_event("OFFER", &in_packet.chaddr, &ip, "existing");
+                    reply(server, config, options::MessageType::Offer, in_packet, &ip);
                     return;
                 }
                 // Otherwise choose a free ip if available
-                for _ in 0..LEASE_NUM {
-                    self.last_lease = (self.last_lease + 1) % LEASE_NUM;
-                    if self.available(
-                        &in_packet.chaddr,
-                        &((IP_START_NUM + &self.last_lease).into()),
-                    ) {
+                let pool_start = config.pool_start_num();
+                for _ in 0..config.pool_size {
+                    state.last_lease = (state.last_lease + 1) % config.pool_size;
+                    let candidate: Ipv4Addr = (pool_start + state.last_lease).into();
+                    if state.available(config, &in_packet.chaddr, &candidate) {
+                        state.log_event("OFFER", &in_packet.chaddr, &candidate, "pool");
                         reply(
                             server,
+                            config,
                             options::MessageType::Offer,
                             in_packet,
-                            &((IP_START_NUM + &self.last_lease).into()),
+                            &candidate,
                         );
-                        break;
+                        return;
                     }
                 }
+                state.log_event(
+                    "NO_OFFER",
+                    &in_packet.chaddr,
+                    &requested_ip,
+                    "pool exhausted",
+                );
             }
 
-            Ok(options::MessageType::Request) => {
+            options::MessageType::Request => {
                 // Ignore requests to alternative DHCP server
                 if !server.for_this_server(&in_packet) {
                     return;
                 }
-                let req_ip = match in_packet.option(options::REQUESTED_IP_ADDRESS) {
-                    Some(options::DhcpOption::RequestedIpAddress(x)) => *x,
-                    _ => in_packet.ciaddr,
-                };
-                if !&self.available(&in_packet.chaddr, &req_ip) {
+                if !state.available(config, &in_packet.chaddr, &requested_ip) {
+                    state.log_event(
+                        "NAK",
+                        &in_packet.chaddr,
+                        &requested_ip,
+                        "Requested IP not available",
+                    );
                     nak(server, in_packet, "Requested IP not available");
                     return;
                 }
-                self.leases.insert(
-                    req_ip,
-                    (in_packet.chaddr, Instant::now().add(self.lease_duration)),
+                let lease_duration = Duration::from_secs(config.lease_secs as u64);
+                state.leases.insert(
+                    requested_ip,
+                    (in_packet.chaddr, Instant::now().add(lease_duration)),
+                );
+                state.log_event("ACK", &in_packet.chaddr, &requested_ip, "");
+                reply(
+                    server,
+                    config,
+                    options::MessageType::Ack,
+                    in_packet,
+                    &requested_ip,
                 );
-                reply(server, options::MessageType::Ack, in_packet, &req_ip);
             }
 
-            Ok(options::MessageType::Release) | Ok(options::MessageType::Decline) => {
+            options::MessageType::Release | options::MessageType::Decline => {
                 // Ignore requests to alternative DHCP server
                 if !server.for_this_server(&in_packet) {
                     return;
                 }
-                if let Some(ip) = self.current_lease(&in_packet.chaddr) {
-                    self.leases.remove(&ip);
+                if let Some(ip) = state.current_lease(&in_packet.chaddr) {
+                    state.leases.remove(&ip);
                 }
             }
 
-            // TODO - not necessary but support for dhcp4r::INFORM might be nice
+            options::MessageType::Inform => {
+                // The client already has an address, only hand out the
+                // network parameters (RFC 2131 section 4.3.5).
+                state.log_event("ACK", &in_packet.chaddr, &in_packet.ciaddr, "inform");
+                inform_ack(server, config, in_packet);
+            }
+
             _ => {}
         }
     }
 }
 
-impl MyServer {
-    fn available(&self, chaddr: &[u8; 6], addr: &Ipv4Addr) -> bool {
+impl LeaseState {
+    fn available(&self, config: &DhcpConfig, chaddr: &[u8; 6], addr: &Ipv4Addr) -> bool {
+        // Reserved addresses only go to their owner, even outside the pool
+        if let Some(owner) = config.reserved_mac(addr) {
+            return owner == *chaddr;
+        }
         let pos: u32 = (*addr).into();
-        pos >= IP_START_NUM
-            && pos < IP_START_NUM + LEASE_NUM
+        let pool_start = config.pool_start_num();
+        pos >= pool_start
+            && pos - pool_start < config.pool_size
             && match self.leases.get(addr) {
                 Some(x) => x.0 == *chaddr || Instant::now().gt(&x.1),
                 None => true,
@@ -162,17 +679,62 @@ impl MyServer {
     }
 
     fn current_lease(&self, chaddr: &[u8; 6]) -> Option<Ipv4Addr> {
-        for (i, v) in &self.leases {
-            if &v.0 == chaddr {
-                return Some(*i);
-            }
+        self.leases
+            .iter()
+            .find(|(_, v)| &v.0 == chaddr)
+            .map(|(ip, _)| *ip)
+    }
+
+    fn log_event(&mut self, event: &str, mac: &[u8; 6], ip: &Ipv4Addr, detail: &str) {
+        let Some(wtr) = self.event_log.as_mut() else {
+            return;
+        };
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+        let result = wtr
+            .write_record([
+                timestamp.as_str(),
+                event,
+                &format_mac(mac),
+                &ip.to_string(),
+                detail,
+            ])
+            .and_then(|_| wtr.flush().map_err(csv::Error::from));
+        if let Err(e) = result {
+            print_warn_ln!("Failed to write DHCP event log: {}", e);
+            self.event_log = None;
         }
-        return None;
+    }
+}
+
+/// Open the event log for appending, so a server restarted in the same test
+/// keeps the earlier events. The header is only written to a new file.
+fn open_event_log(path: &Path) -> Result<csv::Writer<File>, Box<dyn Error>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let is_new = file.metadata()?.len() == 0;
+    let mut wtr = csv::Writer::from_writer(file);
+    if is_new {
+        wtr.write_record(["Timestamp", "Event", "MAC", "IP", "Detail"])?;
+        wtr.flush()?;
+    }
+    Ok(wtr)
+}
+
+fn message_type_name(msg_type: options::MessageType) -> &'static str {
+    match msg_type {
+        options::MessageType::Discover => "DISCOVER",
+        options::MessageType::Offer => "OFFER",
+        options::MessageType::Request => "REQUEST",
+        options::MessageType::Decline => "DECLINE",
+        options::MessageType::Ack => "ACK",
+        options::MessageType::Nak => "NAK",
+        options::MessageType::Release => "RELEASE",
+        options::MessageType::Inform => "INFORM",
     }
 }
 
 fn reply(
-    s: &server::Server,
+    s: &Responder,
+    config: &DhcpConfig,
     msg_type: options::MessageType,
     req_packet: packet::Packet,
     offer_ip: &Ipv4Addr,
@@ -180,17 +742,30 @@ fn reply(
     let _ = s.reply(
         msg_type,
         vec![
-            options::DhcpOption::IpAddressLeaseTime(LEASE_DURATION_SECS),
-            options::DhcpOption::SubnetMask(SUBNET_MASK),
-            options::DhcpOption::Router(vec![ROUTER_IP]),
-            options::DhcpOption::DomainNameServer(DNS_IPS.to_vec()),
+            options::DhcpOption::IpAddressLeaseTime(config.lease_secs),
+            options::DhcpOption::SubnetMask(config.subnet_mask),
+            options::DhcpOption::Router(vec![config.router]),
+            options::DhcpOption::DomainNameServer(config.dns.clone()),
         ],
         *offer_ip,
         req_packet,
     );
 }
 
-fn nak(s: &server::Server, req_packet: packet::Packet, message: &str) {
+fn inform_ack(s: &Responder, config: &DhcpConfig, req_packet: packet::Packet) {
+    let _ = s.reply(
+        options::MessageType::Ack,
+        vec![
+            options::DhcpOption::SubnetMask(config.subnet_mask),
+            options::DhcpOption::Router(vec![config.router]),
+            options::DhcpOption::DomainNameServer(config.dns.clone()),
+        ],
+        Ipv4Addr::new(0, 0, 0, 0),
+        req_packet,
+    );
+}
+
+fn nak(s: &Responder, req_packet: packet::Packet, message: &str) {
     let _ = s.reply(
         options::MessageType::Nak,
         vec![options::DhcpOption::Message(message.to_string())],
@@ -198,3 +773,157 @@ fn nak(s: &server::Server, req_packet: packet::Packet, message: &str) {
         req_packet,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discover(mac: [u8; 6]) -> packet::Packet {
+        packet::Packet {
+            reply: false,
+            hops: 0,
+            xid: 0x1234,
+            secs: 0,
+            broadcast: false,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            siaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: Ipv4Addr::UNSPECIFIED,
+            chaddr: mac,
+            options: vec![options::DhcpOption::DhcpMessageType(
+                options::MessageType::Discover,
+            )],
+        }
+    }
+
+    #[test]
+    fn parse_pool_short_and_full_end() {
+        let (start, size) = parse_pool("192.168.32.40-50").unwrap();
+        assert_eq!(start, Ipv4Addr::new(192, 168, 32, 40));
+        assert_eq!(size, 11);
+        let (_, size) = parse_pool("10.0.0.250-10.0.1.4").unwrap();
+        assert_eq!(size, 11);
+        assert!(parse_pool("192.168.32.50-40").is_err());
+        assert!(parse_pool("192.168.32.40").is_err());
+        assert!(parse_pool("192.168.32.40-300").is_err());
+    }
+
+    #[test]
+    fn parse_mac_separators() {
+        let mac = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+        assert_eq!(parse_mac("aa:bb:cc:dd:ee:ff").unwrap(), mac);
+        assert_eq!(parse_mac("AA-BB-CC-DD-EE-FF").unwrap(), mac);
+        assert_eq!(format_mac(&mac), "aa:bb:cc:dd:ee:ff");
+        assert!(parse_mac("aa:bb:cc:dd:ee").is_err());
+        assert!(parse_mac("aa:bb:cc:dd:ee:zz").is_err());
+    }
+
+    #[test]
+    fn empty_or_wrapping_pool_is_rejected() {
+        let mut config = DhcpConfig {
+            pool_size: 0,
+            ..DhcpConfig::default()
+        };
+        assert!(config.validate().is_err());
+        config.pool_start = Ipv4Addr::new(255, 255, 255, 250);
+        config.pool_size = 7;
+        assert!(config.validate().is_err());
+        config.pool_size = 6;
+        assert!(config.validate().is_ok());
+
+        let mut config = DhcpConfig::default();
+        config
+            .apply_args(&["pool=192.168.32.40-41", "lease=60"])
+            .unwrap();
+        assert_eq!((config.pool_size, config.lease_secs), (2, 60));
+        assert!(config.apply_args(&["pool=192.168.32.40-30"]).is_err());
+        assert!(config.apply_args(&["colour=blue"]).is_err());
+    }
+
+    #[test]
+    fn last_pool_address_is_available() {
+        let config = DhcpConfig {
+            pool_start: Ipv4Addr::new(255, 255, 255, 254),
+            pool_size: 2,
+            ..DhcpConfig::default()
+        };
+        let state = LeaseState::default();
+        let mac = [1, 2, 3, 4, 5, 6];
+        assert!(state.available(&config, &mac, &Ipv4Addr::BROADCAST));
+        assert!(!state.available(&config, &mac, &Ipv4Addr::new(255, 255, 255, 253)));
+    }
+
+    #[test]
+    fn event_log_is_appended_across_restarts() {
+        let path = std::env::temp_dir().join(format!("vtg_dhcp_events_{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mac = [2, 0, 0, 0, 0, 1];
+        let ip = Ipv4Addr::new(192, 168, 32, 41);
+        for event in ["ACK", "RELEASE"] {
+            let mut state = LeaseState {
+                event_log: Some(open_event_log(&path).unwrap()),
+                ..LeaseState::default()
+            };
+            state.log_event(event, &mac, &ip, "");
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Timestamp,Event"));
+        assert!(lines[1].contains(",ACK,02:00:00:00:00:01,192.168.32.41,"));
+        assert!(lines[2].contains(",RELEASE,"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn server_offers_and_stops_under_traffic() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(SERVER_POLL_INTERVAL)).unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let config = Arc::new(DhcpConfig::default());
+        let state = Arc::new(Mutex::new(LeaseState::default()));
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let thread_flag = Arc::clone(&stop_flag);
+        let server = thread::spawn(move || serve_until_stopped(socket, config, state, thread_flag));
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mac = [2, 0, 0, 0, 0, 1];
+        let mut buffer = [0u8; DHCP_BUFFER_SIZE];
+        client
+            .send_to(discover(mac).encode(&mut buffer), server_addr)
+            .unwrap();
+        let (len, _) = client.recv_from(&mut buffer).unwrap();
+        let Ok(offer) = packet::Packet::from(&buffer[..len]) else {
+            panic!("invalid reply packet");
+        };
+        assert!(matches!(
+            offer.message_type(),
+            Ok(options::MessageType::Offer)
+        ));
+        assert_eq!(offer.chaddr, mac);
+        assert_eq!(offer.xid, 0x1234);
+        assert_eq!(offer.yiaddr, Ipv4Addr::new(192, 168, 32, 41));
+
+        // Keep the server busy, it must still see the stop flag
+        let flooding = Arc::new(AtomicBool::new(true));
+        let flood_flag = Arc::clone(&flooding);
+        let flood = thread::spawn(move || {
+            let mut buffer = [0u8; DHCP_BUFFER_SIZE];
+            let request = discover([2, 0, 0, 0, 0, 2]);
+            while flood_flag.load(Ordering::Acquire) {
+                let _ = client.send_to(request.encode(&mut buffer), server_addr);
+            }
+        });
+        thread::sleep(Duration::from_millis(50));
+        let stop_requested = Instant::now();
+        stop_flag.store(true, Ordering::Release);
+        server.join().unwrap();
+        assert!(stop_requested.elapsed() < SERVER_POLL_INTERVAL);
+        flooding.store(false, Ordering::Release);
+        flood.join().unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+
+const LOG_EXTENSION: &str = "log";
+
+// Log of the test being run, external command output is appended to it.
+static TEST_LOG: Mutex<Option<File>> = Mutex::new(None);
+
+fn timestamp() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+/// Open the test log at `path` and make it the target of `log_line`.
+pub fn start_test_log(path: &Path, test_id: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "[{}] ===== Test {} =====", timestamp(), test_id)?;
+
+    *TEST_LOG.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+pub fn stop_test_log() {
+    TEST_LOG.lock().unwrap().take();
+}
+
+/// Append a timestamped line to the log of the running test, if any.
+pub fn log_line(stream: &str, line: &str) {
+    if let Some(file) = TEST_LOG.lock().unwrap().as_mut()
+        && let Err(e) = writeln!(file, "[{}] [{}] {}", timestamp(), stream, line)
+    {
+        print_warn_ln!("Failed to write the test log: {}", e);
+    }
+}
+
+/// Copy the test logs of `logs_dir` into `folder`, replacing the logs copied
+/// there before.
+pub fn copy_logs(logs_dir: &Path, folder: &str) -> Result<usize, Box<dyn Error>> {
+    fs::create_dir_all(folder)?;
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == LOG_EXTENSION) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    if !logs_dir.exists() {
+        return Ok(0);
+    }
+    let mut copied = 0;
+    for entry in fs::read_dir(logs_dir)? {
+        let path = entry?.path();
+        if let Some(name) = path.file_name().filter(|_| path.is_file()) {
+            fs::copy(&path, Path::new(folder).join(name))?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
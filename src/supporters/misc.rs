@@ -1,16 +1,36 @@
 use indicatif::{ProgressBar, ProgressStyle};
 use std::error::Error;
+use std::fs;
 use std::io::{self, Write};
+use std::path::Path;
 use std::thread::sleep;
 use std::time::Duration;
 
-use crate::commands::commands_ccc::get_config_dump;
-use crate::files_ops::excel_ops::{report_sheet_test_id_fail, report_sheet_test_id_pass};
-
-const EMAIL_ATTACHMENTS_FLDR: &str = "./email_attachments/";
-
-pub fn generate_email_attachments() -> Result<(), Box<dyn Error>> {
-    get_config_dump(EMAIL_ATTACHMENTS_FLDR)
+use crate::commands::commands_ccc::{CONFIG_DUMP_PREFIX, get_config_dump};
+
+pub const EMAIL_ATTACHMENTS_FLDR: &str = "./email_attachments/";
+
+/// Dump the sensor configuration in `config_dumps_dir` and stage it in the
+/// email attachments folder, replacing the dump staged before.
+pub fn generate_email_attachments(config_dumps_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let dump_path = get_config_dump(config_dumps_dir)?;
+
+    fs::create_dir_all(EMAIL_ATTACHMENTS_FLDR)?;
+    for entry in fs::read_dir(EMAIL_ATTACHMENTS_FLDR)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file()
+            && entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(CONFIG_DUMP_PREFIX)
+        {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    if let Some(name) = dump_path.file_name() {
+        fs::copy(&dump_path, Path::new(EMAIL_ATTACHMENTS_FLDR).join(name))?;
+    }
+    Ok(())
 }
 
 pub fn press_enter() {
@@ -72,6 +92,26 @@ pub fn human_readable_time(seconds: u32) -> String {
     }
 }
 
+/// Parse a duration written as `<number>[s|m|h|d]`, ex: `20s`, `30m`, `8h`.
+/// A bare number is taken as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, Box<dyn Error>> {
+    let trimmed = value.trim();
+    let (number, multiplier) = match trimmed.chars().last() {
+        Some('s') => (&trimmed[..trimmed.len() - 1], 1),
+        Some('m') => (&trimmed[..trimmed.len() - 1], 60),
+        Some('h') => (&trimmed[..trimmed.len() - 1], 3600),
+        Some('d') => (&trimmed[..trimmed.len() - 1], 86400),
+        _ => (trimmed, 1),
+    };
+    let amount: u64 = number
+        .parse()
+        .map_err(|e| format!("Invalid duration '{}': {}", value, e))?;
+    amount
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Invalid duration '{}': too large", value).into())
+}
+
 pub fn print_thick_separator() {
     println!("=========================================================================");
 }
@@ -91,30 +131,52 @@ pub fn test_pass_prompt() -> Result<u32, Box<dyn Error>> {
     if trimmed == "y" { Ok(1) } else { Ok(0) }
 }
 
-pub fn test_pass_fail_prompt<P: AsRef<std::path::Path>>(
-    xlsx_path: P,
-    test_id: &str,
-) -> Result<(), Box<dyn Error>> {
+/// Prompts for the test verdict and its note. Returns `true` on pass.
+pub fn test_verdict_prompt() -> Result<(bool, String), Box<dyn Error>> {
     let ret = test_pass_prompt()?; // returns 1 for pass, 0 for fail
 
     if ret == 1 {
         let default_note = "The test passed the expected criteria";
-        report_sheet_test_id_pass(xlsx_path, test_id, Some(default_note))?;
+        return Ok((true, default_note.to_string()));
+    }
+
+    print!("Enter optional note (or leave blank for default fail note): ");
+    io::stdout().flush()?;
+    let mut note_input = String::new();
+    io::stdin().read_line(&mut note_input)?;
+    let note = note_input.trim();
+
+    let final_note = if note.is_empty() {
+        "The test did not meet the expected criteria"
     } else {
-        print!("Enter optional note (or leave blank for default fail note): ");
-        io::stdout().flush()?;
-        let mut note_input = String::new();
-        io::stdin().read_line(&mut note_input)?;
-        let note = note_input.trim();
-
-        let final_note = if note.is_empty() {
-            "The test did not meet the expected criteria"
-        } else {
-            note
-        };
-
-        report_sheet_test_id_fail(xlsx_path, test_id, Some(final_note))?;
+        note
+    };
+
+    Ok((false, final_note.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        let secs = |v: &str| parse_duration(v).unwrap().as_secs();
+        assert_eq!(secs("20s"), 20);
+        assert_eq!(secs("30m"), 1800);
+        assert_eq!(secs("8h"), 28800);
+        assert_eq!(secs("2d"), 172800);
+        assert_eq!(secs(" 45 "), 45);
+        assert_eq!(secs("0s"), 0);
     }
 
-    Ok(())
+    #[test]
+    fn parse_duration_errors() {
+        for value in ["", "s", "-5s", "1.5h", "10 min", "ten"] {
+            assert!(parse_duration(value).is_err(), "{}", value);
+        }
+        assert!(parse_duration(&u64::MAX.to_string()).is_ok());
+        let err = parse_duration(&format!("{}d", u64::MAX / 1000)).unwrap_err();
+        assert!(err.to_string().contains("too large"));
+    }
 }
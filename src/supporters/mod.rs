@@ -1,4 +1,8 @@
 pub mod dhcp_server;
+pub mod log_ops;
 pub mod misc;
 pub mod pcap_ops;
+pub mod power_control;
+pub mod process_ops;
 pub mod scripts_find;
+pub mod sensor_config;
@@ -1,4 +1,3 @@
-use std::fs;
 use std::path::PathBuf;
 use std::sync::{
     Arc,
@@ -10,8 +9,9 @@ use std::time::Duration;
 use pcap::{Capture, ConnectionStatus, Device};
 use std::net::IpAddr;
 
+use crate::files_ops::campaign_ops::TestArtifacts;
+
 const HOST_IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 32, 100));
-const PCAP_DIR: &str = "pcaps";
 
 // TODO: Implement an interface flag for (TDB)[default],[short],[long] timeout
 // constants and based on the input flag, let the code decide when to stop the
@@ -52,7 +52,7 @@ fn connected_ethernet_device() -> Option<Device> {
 }
 
 pub struct PcapInstance {
-    test_name: String,
+    pcap_path: PathBuf,
     stop_flag: Arc<AtomicBool>,
     thread_1_handle: Option<JoinHandle<()>>,
     thread_2_handle: Option<JoinHandle<()>>,
@@ -60,25 +60,30 @@ pub struct PcapInstance {
 }
 
 impl PcapInstance {
-    pub fn new(test_name: &str) -> Self {
-        let dir = PathBuf::from(PCAP_DIR);
+    /// Capture to `<artifacts.name>.pcap`. An existing capture is never
+    /// overwritten, the capture is skipped instead.
+    pub fn new(artifacts: &TestArtifacts) -> Self {
         let mut skip = false;
 
-        if let Err(e) = fs::create_dir_all(&dir) {
-            print_warn_ln!("Failed to create {} directory: {}", PCAP_DIR, e);
-            skip = true;
-        }
-
-        let pcap_path = dir.join(format!("{}.pcap", test_name));
-        if pcap_path.exists() {
-            if let Err(e) = fs::remove_file(&pcap_path) {
-                print_warn_ln!("Failed to remove existing pcap file. {}", e);
+        let pcap_path = match artifacts.path(".pcap") {
+            Ok(path) => path,
+            Err(e) => {
+                print_warn_ln!(
+                    "Failed to create {} directory: {}",
+                    artifacts.dir.display(),
+                    e
+                );
                 skip = true;
+                PathBuf::new()
             }
+        };
+        if pcap_path.exists() {
+            print_warn_ln!("Pcap file {} already exists.", pcap_path.display());
+            skip = true;
         }
 
         PcapInstance {
-            test_name: test_name.to_string(),
+            pcap_path,
             stop_flag: Arc::new(AtomicBool::new(false)),
             thread_1_handle: None,
             thread_2_handle: None,
@@ -100,7 +105,7 @@ impl PcapInstance {
             }
         };
 
-        println!("[PCAP] Capture started for: {}", self.test_name);
+        println!("[PCAP] Capture started to: {}", self.pcap_path.display());
 
         // Share the same stop_flag for both threads
         let timer_flag = Arc::clone(&self.stop_flag);
@@ -125,8 +130,7 @@ impl PcapInstance {
         });
 
         // 2) Capture thread: runs until stop_flag becomes true
-        let name = self.test_name.clone();
-        let path = PathBuf::from(PCAP_DIR).join(format!("{}.pcap", name));
+        let path = self.pcap_path.clone();
         let thread_2_handle = thread::spawn(move || {
             let mut cap = Capture::from_device(main_device)
                 .unwrap()
@@ -185,8 +189,8 @@ impl PcapInstance {
         }
 
         println!(
-            "[PCAP] Capture complete. Saved to: {}/{}.pcap",
-            PCAP_DIR, &self.test_name
+            "[PCAP] Capture complete. Saved to: {}",
+            self.pcap_path.display()
         );
     }
 }
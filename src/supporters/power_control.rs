@@ -0,0 +1,412 @@
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use crate::supporters::log_ops::log_line;
+use crate::supporters::misc::press_enter_no_message;
+use crate::supporters::process_ops::run_with_timeout;
+
+// Optional power controller configuration file, looked up in the working directory.
+const POWER_CONFIG_FILE: &str = "power_config.toml";
+const POWER_STREAM: &str = "power";
+const DEFAULT_LINE_ENDING: &str = "\r\n";
+const DEFAULT_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_BAUD: u32 = 9600;
+const PORT_SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the sensor power supply is switched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerControllerKind {
+    /// The technician switches the power when asked to, the default.
+    #[default]
+    Manual,
+    /// A USB/serial relay board driven with ASCII commands.
+    Serial,
+    /// External commands, ex: the CLI of a network power switch.
+    Command,
+}
+
+/// Power controller settings, from `power_config.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    pub controller: PowerControllerKind,
+    /// Serial port of the relay board, ex: `COM3` or `/dev/ttyUSB0`.
+    pub port: String,
+    /// Baud rate of the relay port, 8 data bits, no parity, 1 stop bit.
+    pub baud: u32,
+    /// Relay command, or command line, switching the power on.
+    pub on: String,
+    /// Relay command, or command line, switching the power off.
+    pub off: String,
+    /// Sent after every relay command.
+    pub line_ending: String,
+    /// Text the relay board answers a command with, not checked if unset.
+    pub reply: Option<String>,
+    /// Wait for the relay reply, or for the external command to exit.
+    pub timeout_secs: u64,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        PowerConfig {
+            controller: PowerControllerKind::default(),
+            port: String::new(),
+            baud: DEFAULT_BAUD,
+            on: String::new(),
+            off: String::new(),
+            line_ending: DEFAULT_LINE_ENDING.to_string(),
+            reply: None,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// Switches the sensor power supply.
+pub trait PowerController {
+    fn set_power(&mut self, on: bool) -> Result<(), Box<dyn Error>>;
+}
+
+fn state_name(on: bool) -> &'static str {
+    if on { "ON" } else { "OFF" }
+}
+
+/// Asks the technician, for benches without a relay.
+struct ManualPower {
+    /// Whether the technician can be asked, not from a background or parallel
+    /// block.
+    interactive: bool,
+}
+
+impl PowerController for ManualPower {
+    fn set_power(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        if !self.interactive {
+            return Err(format!(
+                "Cannot ask for the power to be switched {} from a background or parallel block, configure a relay in '{}'",
+                state_name(on),
+                POWER_CONFIG_FILE
+            )
+            .into());
+        }
+        println!(
+            "  - Switch the sensor power {}, then press Enter",
+            state_name(on)
+        );
+        press_enter_no_message();
+        Ok(())
+    }
+}
+
+/// Relay board on a serial port, set to raw mode at `baud` when opened. A
+/// pty works as a fake board.
+struct SerialRelay {
+    port: String,
+    baud: u32,
+    on: String,
+    off: String,
+    line_ending: String,
+    reply: Option<String>,
+    timeout: Duration,
+}
+
+impl SerialRelay {
+    /// Command setting the port to 8N1 at the baud rate. Windows ports have
+    /// no line discipline to turn off.
+    #[cfg(windows)]
+    fn setup_command(&self) -> (&'static str, Vec<String>) {
+        let args = [
+            format!("{}:", self.port.trim_end_matches(':')),
+            format!("BAUD={}", self.baud),
+            "PARITY=n".to_string(),
+            "DATA=8".to_string(),
+            "STOP=1".to_string(),
+        ];
+        ("mode", args.to_vec())
+    }
+
+    /// Command setting the port to raw 8N1 at the baud rate, without echo and
+    /// with reads returning after 100ms without data.
+    #[cfg(not(windows))]
+    fn setup_command(&self) -> (&'static str, Vec<String>) {
+        let args = [
+            "-F".to_string(),
+            self.port.clone(),
+            self.baud.to_string(),
+            "raw".to_string(),
+            "-echo".to_string(),
+            "min".to_string(),
+            "0".to_string(),
+            "time".to_string(),
+            "1".to_string(),
+        ];
+        ("stty", args.to_vec())
+    }
+
+    /// Open the port and configure it, the settings of an open port are kept
+    /// by the system until it is closed.
+    fn open_port(&self) -> Result<File, Box<dyn Error>> {
+        let port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&self.port)
+            .map_err(|e| format!("Failed to open the relay port '{}': {}", self.port, e))?;
+        let (exe, args) = self.setup_command();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let status = run_with_timeout(Path::new(exe), &args, Some(PORT_SETUP_TIMEOUT))?;
+        if !status.success() {
+            return Err(format!(
+                "Failed to set the relay port '{}' to {} baud: {}",
+                self.port, self.baud, status
+            )
+            .into());
+        }
+        Ok(port)
+    }
+
+    /// Read from `port` until `expected` is received or `timeout` passes.
+    fn wait_reply(&self, port: &File, expected: &str) -> Result<(), Box<dyn Error>> {
+        let mut reader = port.try_clone()?;
+        let expected_text = expected.to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader_stop = Arc::clone(&stop);
+        let (sender, receiver) = mpsc::channel();
+        // Reads block, so they run on their own thread, which stops at the
+        // expected reply or once `stop` is set. Reads of a configured port
+        // return every 100ms on Unix, a Windows read returns at the next byte.
+        thread::spawn(move || {
+            let mut buffer = [0u8; 256];
+            let mut received = String::new();
+            while !reader_stop.load(Ordering::Acquire) {
+                match reader.read(&mut buffer) {
+                    Ok(n) => received.push_str(&String::from_utf8_lossy(&buffer[..n])),
+                    Err(e) if matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::TimedOut) => {}
+                    Err(_) => break,
+                }
+                if received.contains(&expected_text) {
+                    break;
+                }
+            }
+            let _ = sender.send(received);
+        });
+
+        let result = receiver.recv_timeout(self.timeout);
+        stop.store(true, Ordering::Release);
+        match result {
+            Ok(received) if received.contains(expected) => {
+                log_line(POWER_STREAM, &format!("reply: {}", received.trim()));
+                Ok(())
+            }
+            Ok(received) => Err(format!(
+                "Relay port '{}' closed before the '{}' reply, received '{}'",
+                self.port,
+                expected,
+                received.trim()
+            )
+            .into()),
+            Err(_) => Err(format!(
+                "No '{}' reply from the relay on '{}' within {}s",
+                expected,
+                self.port,
+                self.timeout.as_secs()
+            )
+            .into()),
+        }
+    }
+}
+
+impl PowerController for SerialRelay {
+    fn set_power(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        let command = if on { &self.on } else { &self.off };
+        let mut port = self.open_port()?;
+        log_line(POWER_STREAM, &format!("{} <- {}", self.port, command));
+        port.write_all(format!("{}{}", command, self.line_ending).as_bytes())?;
+        port.flush()?;
+        match &self.reply {
+            Some(expected) => self.wait_reply(&port, expected),
+            None => Ok(()),
+        }
+    }
+}
+
+/// External command lines switching the power.
+struct CommandRelay {
+    on: String,
+    off: String,
+    timeout: Duration,
+}
+
+impl PowerController for CommandRelay {
+    fn set_power(&mut self, on: bool) -> Result<(), Box<dyn Error>> {
+        let command = if on { &self.on } else { &self.off };
+        let mut parts = command.split_whitespace();
+        let exe = parts.next().ok_or("Empty power command")?;
+        let args: Vec<&str> = parts.collect();
+        let status = run_with_timeout(Path::new(exe), &args, Some(self.timeout))?;
+        if !status.success() {
+            return Err(format!("Power command '{}' failed: {}", command, status).into());
+        }
+        Ok(())
+    }
+}
+
+impl PowerConfig {
+    /// Load `power_config.toml` if present, otherwise switch the power manually.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        if !Path::new(POWER_CONFIG_FILE).exists() {
+            return Ok(PowerConfig::default());
+        }
+        let content = fs::read_to_string(POWER_CONFIG_FILE)?;
+        toml::from_str(&content)
+            .map_err(|e| format!("Invalid power config '{}': {}", POWER_CONFIG_FILE, e).into())
+    }
+
+    /// The controller described by the settings. Unless `interactive`, the
+    /// manual controller fails instead of asking the technician.
+    pub fn controller(
+        &self,
+        interactive: bool,
+    ) -> Result<Box<dyn PowerController>, Box<dyn Error>> {
+        let missing = |field: &str| {
+            format!(
+                "Missing '{}' in '{}' for the {:?} power controller",
+                field, POWER_CONFIG_FILE, self.controller
+            )
+        };
+        let timeout = Duration::from_secs(self.timeout_secs);
+        if self.controller != PowerControllerKind::Manual {
+            if self.on.trim().is_empty() {
+                return Err(missing("on").into());
+            }
+            if self.off.trim().is_empty() {
+                return Err(missing("off").into());
+            }
+        }
+        match self.controller {
+            PowerControllerKind::Manual => Ok(Box::new(ManualPower { interactive })),
+            PowerControllerKind::Serial => {
+                if self.port.is_empty() {
+                    return Err(missing("port").into());
+                }
+                Ok(Box::new(SerialRelay {
+                    port: self.port.clone(),
+                    baud: self.baud,
+                    on: self.on.clone(),
+                    off: self.off.clone(),
+                    line_ending: self.line_ending.clone(),
+                    reply: self.reply.clone(),
+                    timeout,
+                }))
+            }
+            PowerControllerKind::Command => Ok(Box::new(CommandRelay {
+                on: self.on.clone(),
+                off: self.off.clone(),
+                timeout,
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_power_cannot_ask_from_a_block() {
+        let config = PowerConfig::default();
+        let error = config
+            .controller(false)
+            .unwrap()
+            .set_power(true)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("background or parallel block"), "{}", error);
+    }
+
+    #[test]
+    fn serial_controller_needs_a_port() {
+        let config: PowerConfig =
+            toml::from_str("controller = \"serial\"\non = \"ON\"\noff = \"OFF\"").unwrap();
+        assert_eq!(config.baud, DEFAULT_BAUD);
+        assert!(config.controller(true).is_err());
+    }
+
+    // Relay board on a pty, answering `<command> OK` to every command but MUTE
+    #[cfg(unix)]
+    const FAKE_BOARD: &str = r#"
+import os, pty
+master, slave = pty.openpty()
+print(os.ttyname(slave), flush=True)
+pending = b''
+while True:
+    pending += os.read(master, 256)
+    while b'\r\n' in pending:
+        line, pending = pending.split(b'\r\n', 1)
+        if line != b'MUTE':
+            os.write(master, line + b' OK\r\n')
+"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn serial_relay_talks_to_a_pty_board() {
+        use std::io::{BufRead, BufReader};
+        use std::process::{Command, Stdio};
+        use std::time::Instant;
+
+        let Ok(mut board) = Command::new("python3")
+            .args(["-c", FAKE_BOARD])
+            .stdout(Stdio::piped())
+            .spawn()
+        else {
+            eprintln!("python3 not found, skipping the pty relay test");
+            return;
+        };
+        let mut port = String::new();
+        BufReader::new(board.stdout.take().unwrap())
+            .read_line(&mut port)
+            .unwrap();
+        let port = port.trim().to_string();
+
+        let mut relay = SerialRelay {
+            port: port.clone(),
+            baud: 19200,
+            on: "RELAY1 ON".to_string(),
+            off: "MUTE".to_string(),
+            line_ending: DEFAULT_LINE_ENDING.to_string(),
+            reply: Some("OK".to_string()),
+            timeout: Duration::from_secs(1),
+        };
+        let switched_on = relay.set_power(true);
+
+        let start = Instant::now();
+        let switched_off = relay.set_power(false);
+        let elapsed = start.elapsed();
+
+        // The board keeps the pty open, so the settings can be read back
+        let settings = Command::new("stty").args(["-F", &port, "-a"]).output();
+        let _ = board.kill();
+        let _ = board.wait();
+
+        switched_on.unwrap();
+        let error = switched_off.unwrap_err().to_string();
+        assert!(error.contains("No 'OK' reply"), "{}", error);
+        assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+
+        let settings = String::from_utf8_lossy(&settings.unwrap().stdout).into_owned();
+        assert!(settings.contains("speed 19200 baud"), "{}", settings);
+        for flag in ["-icanon", "-echo", "-opost"] {
+            assert!(
+                settings.split_whitespace().any(|f| f == flag),
+                "{}: {}",
+                flag,
+                settings
+            );
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle, sleep};
+use std::time::{Duration, Instant};
+
+use crate::supporters::log_ops::log_line;
+use crate::supporters::misc::parse_duration;
+
+/// Timeout of an external command without a `@timeout=` option. Shortened in
+/// tests so the default can be seen to expire.
+#[cfg(not(test))]
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+#[cfg(test)]
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+/// `@timeout=none`, for commands that must not be killed, ex: a firmware
+/// update.
+pub const NO_TIMEOUT: &str = "none";
+/// Wait before the first retry, doubled on every following retry.
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+const RUN_OPTION_PREFIX: char = '@';
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Stream labels of the test log lines
+const COMMAND_STREAM: &str = "cmd";
+const STDOUT_STREAM: &str = "stdout";
+const STDERR_STREAM: &str = "stderr";
+
+/// Per-instruction options written after the command, ex:
+/// `ccc set pixel_format=0 --force @timeout=10s @retries=2 @backoff=5s`.
+/// The timeout is `None` with `@timeout=none`.
+#[derive(Debug, Clone, Copy)]
+pub struct RunOptions {
+    pub timeout: Option<Duration>,
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            timeout: Some(DEFAULT_COMMAND_TIMEOUT),
+            retries: 0,
+            backoff: DEFAULT_RETRY_BACKOFF,
+        }
+    }
+}
+
+/// Split the `@key=value` run options out of an instruction line. Returns
+/// the line without them.
+pub fn split_run_options(line: &str) -> Result<(String, RunOptions), Box<dyn Error>> {
+    let mut options = RunOptions::default();
+    let mut command = Vec::new();
+
+    for field in line.split_whitespace() {
+        let Some(option) = field.strip_prefix(RUN_OPTION_PREFIX) else {
+            command.push(field);
+            continue;
+        };
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid run option '{}', expected @key=value", field))?;
+        match key {
+            "timeout" if value == NO_TIMEOUT => options.timeout = None,
+            "timeout" => options.timeout = Some(parse_duration(value)?),
+            "retries" => {
+                options.retries = value
+                    .parse()
+                    .map_err(|e| format!("Invalid retries '{}': {}", value, e))?
+            }
+            "backoff" => options.backoff = parse_duration(value)?,
+            _ => {
+                return Err(format!(
+                    "Unknown run option '{}', expected timeout, retries or backoff",
+                    key
+                )
+                .into());
+            }
+        }
+    }
+
+    Ok((command.join(" "), options))
+}
+
+/// Copy the output of a child to the console as it comes, and each line of it
+/// to the test log.
+fn tee_output<R: Read + Send + 'static>(mut reader: R, stream: &'static str) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        let mut pending: Vec<u8> = Vec::new();
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let chunk = &buf[..n];
+            let _ = if stream == STDERR_STREAM {
+                io::stderr().write_all(chunk)
+            } else {
+                io::stdout()
+                    .write_all(chunk)
+                    .and_then(|_| io::stdout().flush())
+            };
+
+            pending.extend_from_slice(chunk);
+            while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=pos).collect();
+                log_line(stream, String::from_utf8_lossy(&line).trim_end());
+            }
+        }
+        if !pending.is_empty() {
+            log_line(stream, String::from_utf8_lossy(&pending).trim_end());
+        }
+    })
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+fn spawn_piped(exe: &Path, args: &[&str]) -> Result<Child, Box<dyn Error>> {
+    Command::new(exe)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", exe.display(), e).into())
+}
+
+/// Wait for `child`, killing it if it runs longer than `timeout`. Returns
+/// None if it was killed.
+fn wait_within(
+    child: &mut Child,
+    timeout: Option<Duration>,
+) -> Result<Option<ExitStatus>, Box<dyn Error>> {
+    let Some(timeout) = timeout else {
+        return Ok(Some(child.wait()?));
+    };
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            // The child may exit between try_wait and kill, ignore that error
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        sleep(CHILD_POLL_INTERVAL);
+    }
+}
+
+fn timed_out(exe: &Path, timeout: Option<Duration>) -> Box<dyn Error> {
+    let message = format!(
+        "{} timed out after {}s and was killed",
+        exe.display(),
+        timeout.unwrap_or_default().as_secs()
+    );
+    log_line(COMMAND_STREAM, &message);
+    message.into()
+}
+
+/// Run `exe` and wait for it, killing it if it runs longer than `timeout`.
+/// Its output goes to the console and to the test log.
+pub fn run_with_timeout(
+    exe: &Path,
+    args: &[&str],
+    timeout: Option<Duration>,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    log_line(
+        COMMAND_STREAM,
+        &format!("{} {}", exe.display(), args.join(" ")),
+    );
+    let mut child = spawn_piped(exe, args)?;
+    let readers: Vec<JoinHandle<()>> = [
+        child
+            .stdout
+            .take()
+            .map(|out| tee_output(out, STDOUT_STREAM)),
+        child
+            .stderr
+            .take()
+            .map(|err| tee_output(err, STDERR_STREAM)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    match wait_within(&mut child, timeout)? {
+        Some(status) => {
+            for reader in readers {
+                let _ = reader.join();
+            }
+            log_line(COMMAND_STREAM, &format!("exited with {}", status));
+            Ok(status)
+        }
+        // Readers are not joined, a process started by the child may still
+        // hold the pipes open.
+        None => Err(timed_out(exe, timeout)),
+    }
+}
+
+/// Run `exe` and return its stdout, killing it if it runs longer than
+/// `timeout`. Fails with its stderr if it exits with an error. The command
+/// and its output go to the test log.
+pub fn output_with_timeout(
+    exe: &Path,
+    args: &[&str],
+    timeout: Option<Duration>,
+) -> Result<String, Box<dyn Error>> {
+    log_line(
+        COMMAND_STREAM,
+        &format!("{} {}", exe.display(), args.join(" ")),
+    );
+    let mut child = spawn_piped(exe, args)?;
+    // Read the pipes while the child runs, a full pipe would block it
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let Some(status) = wait_within(&mut child, timeout)? else {
+        return Err(timed_out(exe, timeout));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    for (stream, output) in [(STDOUT_STREAM, &stdout), (STDERR_STREAM, &stderr)] {
+        for line in output.lines() {
+            log_line(stream, line);
+        }
+    }
+    log_line(COMMAND_STREAM, &format!("exited with {}", status));
+    if status.success() {
+        Ok(stdout)
+    } else {
+        Err(format!(
+            "{} exited with {}: {}",
+            exe.display(),
+            status,
+            stderr.trim()
+        )
+        .into())
+    }
+}
+
+/// Run `exe` with the timeout of `options`, retrying failed or timed out
+/// runs with an exponential backoff. `name` labels the command in errors.
+pub fn run_with_retries(
+    name: &str,
+    exe: &Path,
+    args: &[&str],
+    options: &RunOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut backoff = options.backoff;
+    let mut attempt = 0;
+    loop {
+        let error: Box<dyn Error> = match run_with_timeout(exe, args, options.timeout) {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => format!("{} command error: {}", name, status).into(),
+            Err(e) => e,
+        };
+        if attempt >= options.retries {
+            return Err(error);
+        }
+        attempt += 1;
+        print_warn_ln!(
+            "{}, retry {}/{} in {}s",
+            error,
+            attempt,
+            options.retries,
+            backoff.as_secs()
+        );
+        sleep(backoff);
+        backoff *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_options_default_to_the_default_timeout() {
+        let (command, options) = split_run_options("ccc set pixel_format=0 --force").unwrap();
+        assert_eq!(command, "ccc set pixel_format=0 --force");
+        assert_eq!(options.timeout, Some(DEFAULT_COMMAND_TIMEOUT));
+        assert_eq!(options.retries, 0);
+        assert_eq!(options.backoff, DEFAULT_RETRY_BACKOFF);
+
+        let (command, options) =
+            split_run_options("ccc update-firmware ultra.cepbin @timeout=none").unwrap();
+        assert_eq!(command, "ccc update-firmware ultra.cepbin");
+        assert_eq!(options.timeout, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_without_timeout_is_killed_after_the_default() {
+        let (command, options) = split_run_options("sleep 10").unwrap();
+        let mut parts = command.split_whitespace();
+        let exe = parts.next().unwrap();
+        let args: Vec<&str> = parts.collect();
+
+        let start = Instant::now();
+        let error = run_with_retries("sleep", Path::new(exe), &args, &options)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("timed out"), "{}", error);
+        assert!(start.elapsed() >= DEFAULT_COMMAND_TIMEOUT);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn run_options_are_split_out() {
+        let (command, options) =
+            split_run_options("ccc set pixel_format=0 --force @timeout=10s @retries=2 @backoff=5s")
+                .unwrap();
+        assert_eq!(command, "ccc set pixel_format=0 --force");
+        assert_eq!(options.timeout, Some(Duration::from_secs(10)));
+        assert_eq!(options.retries, 2);
+        assert_eq!(options.backoff, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn invalid_run_options_are_rejected() {
+        assert!(split_run_options("ccc get-all @timeout").is_err());
+        assert!(split_run_options("ccc get-all @timeout=soon").is_err());
+        assert!(split_run_options("ccc get-all @retries=-1").is_err());
+        assert!(split_run_options("ccc get-all @delay=1s").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_is_captured() {
+        let output = output_with_timeout(Path::new("sh"), &["-c", "echo up"], None).unwrap();
+        assert_eq!(output, "up\n");
+        let error = output_with_timeout(Path::new("sh"), &["-c", "echo down >&2; exit 3"], None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("down"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn child_is_killed_after_timeout() {
+        let start = Instant::now();
+        let error = output_with_timeout(
+            Path::new("sleep"),
+            &["10"],
+            Some(Duration::from_millis(200)),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("timed out"), "{}", error);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let status = run_with_timeout(Path::new("sh"), &["-c", "exit 0"], None).unwrap();
+        assert!(status.success());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn captured_output_goes_to_the_test_log() {
+        use crate::supporters::log_ops::{start_test_log, stop_test_log};
+
+        let path = std::env::temp_dir().join(format!("vtg_process_log_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        start_test_log(&path, "1.1").unwrap();
+        let output = output_with_timeout(Path::new("sh"), &["-c", "echo pixel_format=3"], None);
+        stop_test_log();
+        assert_eq!(output.unwrap(), "pixel_format=3\n");
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(log.contains("[cmd] sh -c echo pixel_format=3"), "{}", log);
+        assert!(log.contains("[stdout] pixel_format=3"), "{}", log);
+        assert!(log.contains("[cmd] exited with"), "{}", log);
+    }
+}
@@ -0,0 +1,373 @@
+use indexmap::IndexMap;
+use std::error::Error;
+use std::fmt;
+
+/// A sensor configuration value as printed by ccc, ex: `[3]`, `[0.25]`,
+/// `[1, 2, 3]` or `[ultra]`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    Int(i64),
+    Float(f64),
+    List(Vec<ConfigValue>),
+    Text(String),
+}
+
+/// Fields of a `ccc get` or `get-all` output, in output order.
+pub type ConfigValues = IndexMap<String, ConfigValue>;
+
+/// Split `text` at the commas that are not inside nested brackets.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&text[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&text[start..]);
+    items
+}
+
+fn parse_scalar(text: &str) -> ConfigValue {
+    let text = text.trim();
+    let unquoted = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .or_else(|| text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')));
+    if let Some(unquoted) = unquoted {
+        return ConfigValue::Text(unquoted.to_string());
+    }
+    if let Ok(int) = text.parse::<i64>() {
+        return ConfigValue::Int(int);
+    }
+    if let Some(int) = text
+        .strip_prefix("0x")
+        .and_then(|hex| i64::from_str_radix(hex, 16).ok())
+    {
+        return ConfigValue::Int(int);
+    }
+    // Keep words like `inf` or `nan` as text
+    if text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.')
+        && let Ok(float) = text.parse::<f64>()
+    {
+        return ConfigValue::Float(float);
+    }
+    ConfigValue::Text(text.to_string())
+}
+
+/// Parse a list item, where brackets always make a list, ex: `[3]` in `[[1, 2], [3]]`.
+fn parse_list(text: &str) -> ConfigValue {
+    let text = text.trim();
+    let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) else {
+        return parse_scalar(text);
+    };
+    ConfigValue::List(
+        split_top_level(inner)
+            .into_iter()
+            .filter(|item| !item.trim().is_empty())
+            .map(parse_list)
+            .collect(),
+    )
+}
+
+impl ConfigValue {
+    /// Parse a value: a bracketed comma separated list is a list, a single
+    /// bracketed value is that value, ex: `[3]`.
+    pub fn parse(text: &str) -> ConfigValue {
+        let text = text.trim();
+        let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) else {
+            return parse_scalar(text);
+        };
+        // ccc brackets single values too, ex: `[3]`
+        let items = split_top_level(inner);
+        if items.len() == 1 && !inner.trim().is_empty() {
+            return ConfigValue::parse(items[0]);
+        }
+        parse_list(text)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ConfigValue::Int(int) => Some(*int as f64),
+            ConfigValue::Float(float) => Some(*float),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ConfigValue::Int(int) => Some(*int),
+            _ => None,
+        }
+    }
+
+    /// Value as written in a `ccc set name=value` argument, ex: `[1,2]`.
+    pub fn set_argument(&self) -> String {
+        match self {
+            ConfigValue::List(items) => {
+                let items: Vec<String> = items.iter().map(ConfigValue::set_argument).collect();
+                format!("[{}]", items.join(","))
+            }
+            value => value.to_string(),
+        }
+    }
+
+    /// Value of a TOML field, booleans are written as 0 or 1.
+    pub fn from_toml(value: &toml::Value) -> Result<ConfigValue, Box<dyn Error>> {
+        match value {
+            toml::Value::Integer(int) => Ok(ConfigValue::Int(*int)),
+            toml::Value::Float(float) => Ok(ConfigValue::Float(*float)),
+            toml::Value::Boolean(b) => Ok(ConfigValue::Int(*b as i64)),
+            toml::Value::String(text) => Ok(ConfigValue::Text(text.clone())),
+            toml::Value::Array(items) => Ok(ConfigValue::List(
+                items
+                    .iter()
+                    .map(ConfigValue::from_toml)
+                    .collect::<Result<_, _>>()?,
+            )),
+            other => Err(format!("Unsupported configuration value: {}", other).into()),
+        }
+    }
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigValue::Int(int) => write!(f, "{}", int),
+            // Debug keeps the decimal point of whole floats, ex: 2.0
+            ConfigValue::Float(float) => write!(f, "{:?}", float),
+            ConfigValue::List(items) => {
+                let items: Vec<String> = items.iter().map(ConfigValue::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            ConfigValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+pub fn is_field_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Split a `name: value`, `name = value` or `name [value]` line.
+fn split_field_line(line: &str) -> Option<(&str, &str)> {
+    let separator = line.find([':', '=', '['])?;
+    let name = line[..separator].trim();
+    if !is_field_name(name) {
+        return None;
+    }
+    let value = match line[separator..].strip_prefix([':', '=']) {
+        Some(value) => value,
+        None => &line[separator..],
+    };
+    Some((name, value))
+}
+
+/// Parse the output of `ccc get <fields...>` or `ccc get-all`, or a saved
+/// `get-all` dump. Bracketed values printed without a field name are given
+/// the `requested` names in order, ex: `[123]` for `ccc get serial_number`.
+/// Other lines without a field name are ignored, unless the output is a
+/// single bare value.
+pub fn parse_config_output(
+    output: &str,
+    requested: &[&str],
+) -> Result<ConfigValues, Box<dyn Error>> {
+    let mut values = ConfigValues::new();
+    let mut unnamed = requested.iter();
+    let lines: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+    let single_line = lines.len() == 1;
+
+    for line in lines {
+        match split_field_line(line) {
+            Some((name, value)) => {
+                values.insert(name.to_string(), ConfigValue::parse(value));
+            }
+            None if !single_line && !line.starts_with('[') => {}
+            None => {
+                let name = unnamed
+                    .next()
+                    .ok_or_else(|| format!("Value without a field name in ccc output: {}", line))?;
+                values.insert(name.to_string(), ConfigValue::parse(line));
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// `name=value` argument of `ccc set`, checking that ccc can parse it back.
+pub fn set_assignment(name: &str, value: &ConfigValue) -> Result<String, Box<dyn Error>> {
+    if !is_field_name(name) {
+        return Err(format!("Invalid field name '{}'", name).into());
+    }
+    let argument = value.set_argument();
+    // Fields are separated by commas, only lists may contain one
+    let has_comma = !matches!(value, ConfigValue::List(_)) && argument.contains(',');
+    if argument.is_empty() || has_comma || argument.contains(char::is_whitespace) {
+        return Err(format!("Cannot set '{}' to '{}' with ccc set", name, argument).into());
+    }
+    Ok(format!("{}={}", name, argument))
+}
+
+/// Fields of a TOML table, ex: `pixel_format = 0`.
+pub fn parse_config_toml(table: &toml::Table) -> Result<ConfigValues, Box<dyn Error>> {
+    let mut values = ConfigValues::new();
+    for (name, value) in table {
+        let value = ConfigValue::from_toml(value)
+            .map_err(|e| format!("Invalid value of field '{}': {}", name, e))?;
+        values.insert(name.clone(), value);
+    }
+    Ok(values)
+}
+
+/// A field whose value differs between two configurations. `None` is a field
+/// missing from that configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub field: String,
+    pub before: Option<ConfigValue>,
+    pub after: Option<ConfigValue>,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |v: &Option<ConfigValue>| match v {
+            Some(v) => v.to_string(),
+            None => "(missing)".to_string(),
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.field,
+            value(&self.before),
+            value(&self.after)
+        )
+    }
+}
+
+/// Fields changed, added or removed from `before` to `after`, in the order of
+/// `before` then of the fields only in `after`.
+pub fn diff_config(before: &ConfigValues, after: &ConfigValues) -> Vec<ConfigChange> {
+    let mut changes: Vec<ConfigChange> = before
+        .iter()
+        .filter(|(field, value)| after.get(*field) != Some(*value))
+        .map(|(field, value)| ConfigChange {
+            field: field.clone(),
+            before: Some(value.clone()),
+            after: after.get(field).cloned(),
+        })
+        .collect();
+    changes.extend(
+        after
+            .iter()
+            .filter(|(field, _)| !before.contains_key(*field))
+            .map(|(field, value)| ConfigChange {
+                field: field.clone(),
+                before: None,
+                after: Some(value.clone()),
+            }),
+    );
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[i64]) -> ConfigValue {
+        ConfigValue::List(items.iter().map(|i| ConfigValue::Int(*i)).collect())
+    }
+
+    #[test]
+    fn values_are_parsed() {
+        assert_eq!(ConfigValue::parse("[3]"), ConfigValue::Int(3));
+        assert_eq!(ConfigValue::parse(" -7 "), ConfigValue::Int(-7));
+        assert_eq!(ConfigValue::parse("0x1F"), ConfigValue::Int(31));
+        assert_eq!(ConfigValue::parse("[0.25]"), ConfigValue::Float(0.25));
+        assert_eq!(ConfigValue::parse("[1, 2, 3]"), list(&[1, 2, 3]));
+        assert_eq!(
+            ConfigValue::parse("1,2"),
+            ConfigValue::Text("1,2".to_string())
+        );
+        assert_eq!(ConfigValue::parse("[]"), ConfigValue::List(vec![]));
+        assert_eq!(
+            ConfigValue::parse("[[1, 2], [3]]"),
+            ConfigValue::List(vec![list(&[1, 2]), list(&[3])])
+        );
+        assert_eq!(
+            ConfigValue::parse("[ultra]"),
+            ConfigValue::Text("ultra".to_string())
+        );
+        assert_eq!(
+            ConfigValue::parse("\"1.2.3\""),
+            ConfigValue::Text("1.2.3".to_string())
+        );
+        assert_eq!(
+            ConfigValue::parse("inf"),
+            ConfigValue::Text("inf".to_string())
+        );
+    }
+
+    #[test]
+    fn named_fields_are_parsed() {
+        let output =
+            "# sensor 1\npixel_format: [3]\nroi = [0, 0, 100, 100]\nname [ultra]\nReady.\n";
+        let values = parse_config_output(output, &[]).unwrap();
+        let fields: Vec<&str> = values.keys().map(String::as_str).collect();
+        assert_eq!(fields, ["pixel_format", "roi", "name"]);
+        assert_eq!(values["pixel_format"], ConfigValue::Int(3));
+        assert_eq!(values["roi"], list(&[0, 0, 100, 100]));
+        assert_eq!(values["name"], ConfigValue::Text("ultra".to_string()));
+    }
+
+    #[test]
+    fn unnamed_values_take_the_requested_names() {
+        let values =
+            parse_config_output("[123]\n[4]\n", &["serial_number", "pixel_format"]).unwrap();
+        assert_eq!(values["serial_number"], ConfigValue::Int(123));
+        assert_eq!(values["pixel_format"], ConfigValue::Int(4));
+
+        let values = parse_config_output("123\n", &["serial_number"]).unwrap();
+        assert_eq!(values["serial_number"], ConfigValue::Int(123));
+
+        assert!(parse_config_output("[1]\n[2]\n", &["serial_number"]).is_err());
+    }
+
+    #[test]
+    fn set_assignments_are_checked() {
+        assert_eq!(set_assignment("roi", &list(&[1, 2])).unwrap(), "roi=[1,2]");
+        assert_eq!(
+            set_assignment("pixel_format", &ConfigValue::Int(3)).unwrap(),
+            "pixel_format=3"
+        );
+        assert!(set_assignment("1field", &ConfigValue::Int(3)).is_err());
+        assert!(set_assignment("name", &ConfigValue::Text("a b".to_string())).is_err());
+        assert!(set_assignment("name", &ConfigValue::Text("a,b".to_string())).is_err());
+    }
+
+    #[test]
+    fn diff_lists_changed_removed_then_added_fields() {
+        let before = parse_config_output("a: [1]\nb: [2]\nc: [x]\n", &[]).unwrap();
+        let after = parse_config_output("d: [4]\nc: [x]\na: [5]\n", &[]).unwrap();
+        let changes = diff_config(&before, &after);
+        let printed: Vec<String> = changes.iter().map(ConfigChange::to_string).collect();
+        assert_eq!(
+            printed,
+            ["a: 1 -> 5", "b: 2 -> (missing)", "d: (missing) -> 4"]
+        );
+        assert!(diff_config(&before, &before).is_empty());
+    }
+}
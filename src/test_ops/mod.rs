@@ -1,2 +1,5 @@
+pub mod test_ops_config_snapshots;
+pub mod test_ops_control_flow;
 pub mod test_ops_process_instructions;
 pub mod test_ops_process_tests;
+pub mod test_ops_run;
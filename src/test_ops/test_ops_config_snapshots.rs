@@ -0,0 +1,229 @@
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::commands::commands_ccc::save_config_snapshot;
+use crate::files_ops::campaign_ops::TestArtifacts;
+use crate::supporters::log_ops::log_line;
+use crate::supporters::sensor_config::{
+    ConfigChange, ConfigValues, diff_config, parse_config_output,
+};
+
+// Optional snapshot configuration file, looked up in the working directory.
+const SNAPSHOT_CONFIG_FILE: &str = "config_snapshot.toml";
+const CONFIG_STREAM: &str = "config";
+const DIFF_STAGE: &str = "diff";
+// Changed fields listed on the console, the diff file has all of them
+const MAX_LISTED_CHANGES: usize = 10;
+
+/// When a test snapshots the sensor configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotStage {
+    Before,
+    BeforeTeardown,
+    After,
+}
+
+impl SnapshotStage {
+    /// Name of the stage in the snapshot file name.
+    fn as_str(&self) -> &'static str {
+        match self {
+            SnapshotStage::Before => "before",
+            SnapshotStage::BeforeTeardown => "before_teardown",
+            SnapshotStage::After => "after",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            SnapshotStage::Before => "before the test",
+            SnapshotStage::BeforeTeardown => "before the teardown",
+            SnapshotStage::After => "after the test",
+        }
+    }
+}
+
+/// Snapshot settings. Fields changing on their own, ex: an uptime or a
+/// temperature, go in `ignore_fields` so they are never reported as changed.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    pub enabled: bool,
+    pub ignore_fields: Vec<String>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            enabled: true,
+            ignore_fields: Vec::new(),
+        }
+    }
+}
+
+impl SnapshotConfig {
+    /// Load `config_snapshot.toml` if present, otherwise use the defaults.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        if !Path::new(SNAPSHOT_CONFIG_FILE).exists() {
+            return Ok(SnapshotConfig::default());
+        }
+        let content = fs::read_to_string(SNAPSHOT_CONFIG_FILE)?;
+        toml::from_str(&content).map_err(|e| {
+            format!("Invalid snapshot config '{}': {}", SNAPSHOT_CONFIG_FILE, e).into()
+        })
+    }
+
+    /// Fields of a snapshot file, without the ignored ones.
+    fn read(&self, path: &Path) -> Result<ConfigValues, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+        let mut values = parse_config_output(&content, &[])?;
+        values.retain(|field, _| !self.ignore_fields.contains(field));
+        Ok(values)
+    }
+}
+
+fn enabled_config() -> Option<SnapshotConfig> {
+    match SnapshotConfig::load() {
+        Ok(config) => config.enabled.then_some(config),
+        Err(e) => {
+            print_warn_ln!("Sensor configuration snapshots disabled: {}", e);
+            None
+        }
+    }
+}
+
+/// Save the sensor configuration at `stage` of a test, ex:
+/// `config_dumps/1.1_attempt1_config_before.txt`. The first snapshot of a
+/// campaign is also kept as its baseline. A failing snapshot does not fail the
+/// test, it is only reported.
+pub fn snapshot_config(artifacts: &TestArtifacts, stage: SnapshotStage) {
+    if enabled_config().is_none() {
+        return;
+    }
+    let path = match artifacts.config_snapshot_path(stage.as_str()) {
+        Ok(path) => path,
+        Err(e) => {
+            print_warn_ln!("Failed to snapshot the sensor configuration: {}", e);
+            return;
+        }
+    };
+    match save_config_snapshot(&path) {
+        Ok(values) => log_line(
+            CONFIG_STREAM,
+            &format!(
+                "Snapshot {}: {} field(s) in {}",
+                stage.describe(),
+                values.len(),
+                path.display()
+            ),
+        ),
+        Err(e) => {
+            let message = format!(
+                "Failed to snapshot the sensor configuration {}: {}",
+                stage.describe(),
+                e
+            );
+            print_warn_ln!("{}", message);
+            log_line(CONFIG_STREAM, &message);
+            return;
+        }
+    }
+
+    let baseline = artifacts.baseline_config_path();
+    if stage == SnapshotStage::Before
+        && !baseline.exists()
+        && let Err(e) = fs::copy(&path, &baseline)
+    {
+        print_warn_ln!("Failed to save the campaign configuration baseline: {}", e);
+    }
+}
+
+fn write_diff(
+    artifacts: &TestArtifacts,
+    sections: &[(&str, Vec<ConfigChange>)],
+) -> Result<(), Box<dyn Error>> {
+    let mut content = String::new();
+    for (title, changes) in sections {
+        content.push_str(&format!("# {}: {} field(s)\n", title, changes.len()));
+        for change in changes {
+            content.push_str(&format!("{}\n", change));
+        }
+        content.push('\n');
+    }
+    fs::write(artifacts.config_snapshot_path(DIFF_STAGE)?, content)?;
+    Ok(())
+}
+
+/// Diff the snapshots of a test into `<name>_config_diff.txt`, and warn when
+/// the test left the sensor configuration different from the campaign
+/// baseline, which breaks the default configuration the next tests expect.
+/// Returns a note for the report in that case.
+pub fn check_config_snapshots(artifacts: &TestArtifacts) -> Option<String> {
+    let config = enabled_config()?;
+    let load = |path: &Path| -> Option<ConfigValues> {
+        if !path.exists() {
+            return None;
+        }
+        match config.read(path) {
+            Ok(values) => Some(values),
+            Err(e) => {
+                print_warn_ln!("Failed to read a configuration snapshot: {}", e);
+                None
+            }
+        }
+    };
+    let stage = |stage: SnapshotStage| {
+        artifacts
+            .config_snapshot_path(stage.as_str())
+            .ok()
+            .and_then(|path| load(&path))
+    };
+    let before = stage(SnapshotStage::Before)?;
+    let after = stage(SnapshotStage::After)?;
+    let baseline = load(&artifacts.baseline_config_path()).unwrap_or_else(|| before.clone());
+
+    let mut sections = Vec::new();
+    if let Some(before_teardown) = stage(SnapshotStage::BeforeTeardown) {
+        sections.push((
+            "Changed by the instructions",
+            diff_config(&before, &before_teardown),
+        ));
+    }
+    sections.push(("Changed by the test", diff_config(&before, &after)));
+    let left = diff_config(&baseline, &after);
+    sections.push(("Different from the campaign baseline", left.clone()));
+    if let Err(e) = write_diff(artifacts, &sections) {
+        print_warn_ln!("Failed to write the configuration diff: {}", e);
+    }
+
+    if left.is_empty() {
+        log_line(CONFIG_STREAM, "Configuration matches the campaign baseline");
+        return None;
+    }
+    print_warn_ln!(
+        "The test left {} sensor configuration field(s) different from the campaign baseline:",
+        left.len()
+    );
+    for change in &left {
+        log_line(CONFIG_STREAM, &format!("Left changed: {}", change));
+    }
+    for change in left.iter().take(MAX_LISTED_CHANGES) {
+        println!("  - {}", change);
+    }
+    if left.len() > MAX_LISTED_CHANGES {
+        println!(
+            "  - ... and {} more in {}_config_{}.txt",
+            left.len() - MAX_LISTED_CHANGES,
+            artifacts.name,
+            DIFF_STAGE
+        );
+    }
+
+    let fields: Vec<&str> = left.iter().map(|c| c.field.as_str()).collect();
+    Some(format!(
+        "Sensor configuration left non-default: {}",
+        fields.join(", ")
+    ))
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::commands::commands_ccc::{ccc_get_all, ccc_get_value};
+use crate::supporters::misc::parse_duration;
+use crate::supporters::sensor_config::ConfigValue;
+
+pub const IF_KEYWORD: &str = "if";
+pub const ELSE_KEYWORD: &str = "else";
+pub const REPEAT_KEYWORD: &str = "repeat";
+pub const UNTIL_KEYWORD: &str = "until";
+pub const ASSERT_KEYWORD: &str = "assert";
+pub const LET_KEYWORD: &str = "let";
+
+const VARIABLE_PREFIX: char = '$';
+const CCC_GET_PREFIX: &str = "ccc get ";
+const CCC_GET_ALL: &str = "ccc get-all";
+const UNTIL_TIMEOUT_OPTION: &str = "timeout=";
+const UNTIL_POLL_OPTION: &str = "poll=";
+const DEFAULT_UNTIL_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_UNTIL_POLL: Duration = Duration::from_secs(1);
+
+/// Values captured by `let` instructions, referenced as `$name` in later lines.
+pub type Variables = HashMap<String, ConfigValue>;
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Replace every `$name` in `line` by the value of the variable. A `$`
+/// not followed by a name is kept as is.
+pub fn substitute_variables(line: &str, variables: &Variables) -> Result<String, Box<dyn Error>> {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find(VARIABLE_PREFIX) {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find(|c: char| !is_variable_char(c))
+            .unwrap_or(after.len());
+        let name = &after[..end];
+        if name.is_empty() {
+            result.push(VARIABLE_PREFIX);
+        } else {
+            let value = variables
+                .get(name)
+                .ok_or_else(|| format!("Unknown variable '${}' in '{}'", name, line))?;
+            result.push_str(&value.to_string());
+        }
+        rest = &after[end..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// Value of a `let` right hand side or assertion operand: the field read
+/// with `ccc get ...`, or the value written in the instruction.
+fn evaluate_operand(text: &str) -> Result<ConfigValue, Box<dyn Error>> {
+    match text.strip_prefix(CCC_GET_PREFIX) {
+        Some(args) => ccc_get_value(args),
+        None => Ok(ConfigValue::parse(text)),
+    }
+}
+
+/// `let <name> = <ccc get ...|value>`, stores the value in `variables`.
+/// `let <name> = ccc get-all` stores every field as `<name>_<field>`.
+pub fn let_handler(trimmed_line: &str, variables: &mut Variables) -> Result<(), Box<dyn Error>> {
+    let usage = "Usage: let <name> = <ccc get ...|value>";
+    let rest = trimmed_line.strip_prefix(LET_KEYWORD).ok_or(usage)?.trim();
+    let (name, expression) = rest.split_once('=').ok_or(usage)?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(is_variable_char) {
+        return Err(format!(
+            "Invalid variable name '{}', use letters, digits and _",
+            name
+        )
+        .into());
+    }
+
+    let expression = expression.trim();
+    if expression == CCC_GET_ALL {
+        let values = ccc_get_all()?;
+        println!("  - ${}_* = {} field(s) of ccc get-all", name, values.len());
+        for (field, value) in values {
+            let field: String = field
+                .chars()
+                .map(|c| if is_variable_char(c) { c } else { '_' })
+                .collect();
+            variables.insert(format!("{}_{}", name, field), value);
+        }
+        return Ok(());
+    }
+
+    let value = evaluate_operand(expression)?;
+    println!("  - ${} = {}", name, value);
+    variables.insert(name.to_string(), value);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    fn parse(token: &str) -> Option<Self> {
+        match token {
+            "==" => Some(CompareOp::Eq),
+            "!=" => Some(CompareOp::Ne),
+            "<" => Some(CompareOp::Lt),
+            "<=" => Some(CompareOp::Le),
+            ">" => Some(CompareOp::Gt),
+            ">=" => Some(CompareOp::Ge),
+            _ => None,
+        }
+    }
+}
+
+/// A comparison `<lhs> <op> <rhs>`, ex: `ccc get pixel_format == 3` or
+/// `$sn != 0`. Operands are compared as numbers when both are numbers, as
+/// text otherwise, ex: lists as `[1, 2]`.
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    lhs: String,
+    op: CompareOp,
+    rhs: String,
+    text: String,
+}
+
+impl Assertion {
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let ops: Vec<usize> = tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| CompareOp::parse(t).is_some())
+            .map(|(idx, _)| idx)
+            .collect();
+        let [op_idx] = ops[..] else {
+            return Err(format!(
+                "Invalid assertion '{}', expected <lhs> ==|!=|<|<=|>|>= <rhs>",
+                text
+            )
+            .into());
+        };
+        if op_idx == 0 || op_idx == tokens.len() - 1 {
+            return Err(format!("Missing operand in assertion '{}'", text).into());
+        }
+
+        Ok(Assertion {
+            lhs: tokens[..op_idx].join(" "),
+            op: CompareOp::parse(tokens[op_idx]).unwrap_or(CompareOp::Eq),
+            rhs: tokens[op_idx + 1..].join(" "),
+            text: tokens.join(" "),
+        })
+    }
+
+    /// Evaluate the assertion, returns whether it holds and the compared
+    /// values for messages.
+    pub fn evaluate(&self) -> Result<(bool, String), Box<dyn Error>> {
+        let lhs = evaluate_operand(&self.lhs)?;
+        let rhs = evaluate_operand(&self.rhs)?;
+        let values = format!("{} vs {}", lhs, rhs);
+
+        let holds = match (lhs.as_f64(), rhs.as_f64()) {
+            (Some(l), Some(r)) => match self.op {
+                CompareOp::Eq => l == r,
+                CompareOp::Ne => l != r,
+                CompareOp::Lt => l < r,
+                CompareOp::Le => l <= r,
+                CompareOp::Gt => l > r,
+                CompareOp::Ge => l >= r,
+            },
+            _ => match self.op {
+                CompareOp::Eq => lhs.to_string() == rhs.to_string(),
+                CompareOp::Ne => lhs.to_string() != rhs.to_string(),
+                _ => {
+                    return Err(format!(
+                        "Cannot order non numeric values in '{}': {}",
+                        self.text, values
+                    )
+                    .into());
+                }
+            },
+        };
+        Ok((holds, values))
+    }
+}
+
+/// `if <assertion>` header of a conditional block.
+pub fn if_condition(trimmed_line: &str) -> Result<bool, Box<dyn Error>> {
+    let condition = trimmed_line
+        .strip_prefix(IF_KEYWORD)
+        .ok_or("Usage: if <assertion>")?;
+    let assertion = Assertion::parse(condition)?;
+    let (holds, values) = assertion.evaluate()?;
+    println!("  - if {}: {} ({})", assertion.text, holds, values);
+    Ok(holds)
+}
+
+/// `repeat <count>` header of a loop block.
+pub fn repeat_count(trimmed_line: &str) -> Result<u32, Box<dyn Error>> {
+    let count = trimmed_line
+        .strip_prefix(REPEAT_KEYWORD)
+        .ok_or("Usage: repeat <count>")?
+        .trim();
+    count
+        .parse()
+        .map_err(|e| format!("Invalid repeat count '{}': {}", count, e).into())
+}
+
+/// `assert <assertion>`, fails the step if the assertion does not hold.
+pub fn assert_handler(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
+    let condition = trimmed_line
+        .strip_prefix(ASSERT_KEYWORD)
+        .ok_or("Usage: assert <assertion>")?;
+    let assertion = Assertion::parse(condition)?;
+    let (holds, values) = assertion.evaluate()?;
+    if !holds {
+        return Err(format!("Assertion '{}' failed: {}", assertion.text, values).into());
+    }
+    println!("  - Assertion '{}' holds ({})", assertion.text, values);
+    Ok(())
+}
+
+/// Split the `timeout=` and `poll=` options out of an `until` line.
+fn split_until_options(line: &str) -> Result<(String, Duration, Duration), Box<dyn Error>> {
+    let mut timeout = DEFAULT_UNTIL_TIMEOUT;
+    let mut poll = DEFAULT_UNTIL_POLL;
+    let mut condition = Vec::new();
+    for field in line.split_whitespace() {
+        if let Some(value) = field.strip_prefix(UNTIL_TIMEOUT_OPTION) {
+            timeout = parse_duration(value)?;
+        } else if let Some(value) = field.strip_prefix(UNTIL_POLL_OPTION) {
+            poll = parse_duration(value)?;
+        } else {
+            condition.push(field);
+        }
+    }
+    Ok((condition.join(" "), timeout, poll))
+}
+
+/// Timeout of an `until` line, for the duration estimate.
+pub fn until_timeout(trimmed_line: &str) -> Duration {
+    split_until_options(trimmed_line)
+        .map(|(_, timeout, _)| timeout)
+        .unwrap_or(DEFAULT_UNTIL_TIMEOUT)
+}
+
+/// `until <assertion> [timeout=30s] [poll=1s]`, evaluates the assertion every
+/// `poll` until it holds, and fails the step after `timeout`. `variables` are
+/// substituted into the assertion before every evaluation.
+pub fn until_handler(trimmed_line: &str, variables: &Variables) -> Result<(), Box<dyn Error>> {
+    let rest = trimmed_line
+        .strip_prefix(UNTIL_KEYWORD)
+        .ok_or("Usage: until <assertion> [timeout=30s] [poll=1s]")?;
+    let (condition, timeout, poll) = split_until_options(rest)?;
+    Assertion::parse(&condition)?;
+
+    println!(
+        "  - Waiting up to {}s until {}",
+        timeout.as_secs(),
+        condition
+    );
+    let start = Instant::now();
+    loop {
+        let assertion = Assertion::parse(&substitute_variables(&condition, variables)?)?;
+        // A failing evaluation, ex: the sensor not answering yet, is retried
+        let last = match assertion.evaluate() {
+            Ok((true, values)) => {
+                println!(
+                    "  - '{}' met after {}s ({})",
+                    assertion.text,
+                    start.elapsed().as_secs(),
+                    values
+                );
+                return Ok(());
+            }
+            Ok((false, values)) => values,
+            Err(e) => e.to_string(),
+        };
+        if start.elapsed() >= timeout {
+            return Err(format!(
+                "'{}' not met within {}s, last: {}",
+                assertion.text,
+                timeout.as_secs(),
+                last
+            )
+            .into());
+        }
+        sleep(poll);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> Variables {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), ConfigValue::parse(value)))
+            .collect()
+    }
+
+    #[test]
+    fn variables_are_substituted() {
+        let vars = variables(&[("sn", "1234"), ("cfg_pixel_format", "3")]);
+        assert_eq!(
+            substitute_variables("ccc set serial=$sn,pf=$cfg_pixel_format", &vars).unwrap(),
+            "ccc set serial=1234,pf=3"
+        );
+        assert_eq!(
+            substitute_variables("echo $ and $$sn", &vars).unwrap(),
+            "echo $ and $1234"
+        );
+        assert!(substitute_variables("ccc get $missing", &vars).is_err());
+    }
+
+    #[test]
+    fn assertions_are_parsed() {
+        let assertion = Assertion::parse(" ccc get pixel_format  ==  3 ").unwrap();
+        assert_eq!(assertion.lhs, "ccc get pixel_format");
+        assert_eq!(assertion.op, CompareOp::Eq);
+        assert_eq!(assertion.rhs, "3");
+        assert_eq!(assertion.text, "ccc get pixel_format == 3");
+
+        let assertion = Assertion::parse("1 >= 0").unwrap();
+        assert_eq!(assertion.op, CompareOp::Ge);
+    }
+
+    #[test]
+    fn invalid_assertions_are_rejected() {
+        for text in [
+            "",
+            "ccc get pixel_format",
+            "== 3",
+            "1 <",
+            "1 < 2 < 3",
+            "1 = 1",
+        ] {
+            assert!(Assertion::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn assertions_compare_numbers_then_text() {
+        let holds = |text: &str| Assertion::parse(text).unwrap().evaluate().unwrap().0;
+        assert!(holds("0x10 == 16"));
+        assert!(holds("1.5 < 2"));
+        assert!(holds("3 != 4"));
+        assert!(holds("[0, 0, 100, 100] == [0, 0, 100, 100]"));
+        assert!(!holds("abc == abd"));
+        assert!(Assertion::parse("abc < abd").unwrap().evaluate().is_err());
+    }
+
+    #[test]
+    fn until_options_are_split_out() {
+        let (condition, timeout, poll) =
+            split_until_options(" ccc get pixel_format == 3 timeout=5s poll=2s").unwrap();
+        assert_eq!(condition, "ccc get pixel_format == 3");
+        assert_eq!(timeout, Duration::from_secs(5));
+        assert_eq!(poll, Duration::from_secs(2));
+        assert_eq!(until_timeout("until 1 == 1"), DEFAULT_UNTIL_TIMEOUT);
+    }
+
+    #[test]
+    fn until_substitutes_the_variables() {
+        let vars = variables(&[("target", "3"), ("current", "3")]);
+        until_handler("until $current == $target timeout=1s poll=1s", &vars).unwrap();
+
+        let vars = variables(&[("target", "4"), ("current", "3")]);
+        let error = until_handler("until $current == $target timeout=0s poll=0s", &vars)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("not met"), "{}", error);
+
+        assert!(until_handler("until $missing == 1 timeout=1s", &vars).is_err());
+    }
+}
@@ -1,11 +1,51 @@
 use std::error::Error;
-use toml::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-use crate::commands::commands_ccc::{ccc_handler, factory_init};
+use chrono::{DateTime, Local};
+
+use crate::commands::commands_ccc::{
+    WAIT_SENSOR_UP_KEYWORD, ccc_handler, factory_init, wait_sensor_up_options, wait_until_sensor_up,
+};
+use crate::commands::commands_config::{
+    APPLY_PROFILE_KEYWORD, RESTORE_BASELINE_KEYWORD, apply_profile, restore_baseline,
+};
+use crate::commands::commands_dhcp::dhcp_handler;
+use crate::commands::commands_firmware::{
+    FLASH_FIRMWARE_KEYWORD, flash_firmware, flash_firmware_duration,
+};
 use crate::commands::commands_generic::generic_runner;
 use crate::commands::commands_panorama::panorama_cli_handler;
+use crate::commands::commands_power::{POWER_KEYWORD, power_cycle_duration, power_handler};
+use crate::files_ops::campaign_ops::TestArtifacts;
+use crate::files_ops::snippet_ops::INCLUDE_KEYWORD;
+use crate::supporters::dhcp_server::DhcpServerHandle;
+use crate::supporters::log_ops::log_line;
 use crate::supporters::misc::{get_key_entry_y, human_readable_time, print_thin_separator, wait_s};
 use crate::supporters::pcap_ops::PcapInstance;
+use crate::test_ops::test_ops_config_snapshots::{SnapshotStage, snapshot_config};
+use crate::test_ops::test_ops_control_flow::{
+    ASSERT_KEYWORD, ELSE_KEYWORD, IF_KEYWORD, LET_KEYWORD, REPEAT_KEYWORD, UNTIL_KEYWORD,
+    Variables, assert_handler, if_condition, let_handler, repeat_count, substitute_variables,
+    until_handler, until_timeout,
+};
+
+const ON_FAIL_OPTION: &str = "on_fail=";
+const EVENT_TIMED_STREAM: &str = "event_timed";
+const EVENT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const BACKGROUND_KEYWORD: &str = "background";
+const PARALLEL_KEYWORD: &str = "parallel";
+const END_KEYWORD: &str = "end";
+/// Keywords opening a block of instructions closed by `end`.
+const BLOCK_KEYWORDS: &[&str] = &[
+    BACKGROUND_KEYWORD,
+    PARALLEL_KEYWORD,
+    IF_KEYWORD,
+    REPEAT_KEYWORD,
+];
 
 const COMMAND_KEYWORDS: &[&str] = &[
     "SEMI_AUTO",
@@ -13,13 +53,74 @@ const COMMAND_KEYWORDS: &[&str] = &[
     // Add more as needed
 ];
 
-fn event_timed(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
+/// What `event_timed` does when one iteration fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnFail {
+    /// Stop the loop and fail the step, the default.
+    Abort,
+    /// Keep looping, the step fails at the end if any iteration failed.
+    Continue,
+}
+
+impl OnFail {
+    fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        match value {
+            "abort" => Ok(OnFail::Abort),
+            "continue" => Ok(OnFail::Continue),
+            _ => Err(format!("Invalid on_fail '{}', expected continue or abort", value).into()),
+        }
+    }
+}
+
+/// Result of one iteration of an `event_timed` loop.
+struct EventIteration {
+    timestamp: DateTime<Local>,
+    error: Option<String>,
+}
+
+/// Print the success rate of an `event_timed` loop and the time of its first
+/// and last failures.
+fn event_timed_summary(command_line: &str, iterations: &[EventIteration]) -> usize {
+    let failures: Vec<&EventIteration> = iterations.iter().filter(|i| i.error.is_some()).collect();
+    let succeeded = iterations.len() - failures.len();
+    let rate = if iterations.is_empty() {
+        0.0
+    } else {
+        succeeded as f64 * 100.0 / iterations.len() as f64
+    };
+    let summary = format!(
+        "Timed event '{}': {}/{} iterations succeeded ({:.1}%)",
+        command_line,
+        succeeded,
+        iterations.len(),
+        rate
+    );
+    println!("{}", summary);
+    log_line(EVENT_TIMED_STREAM, &summary);
+    if let (Some(first), Some(last)) = (failures.first(), failures.last()) {
+        let times = format!(
+            "First failure at {}, last failure at {}",
+            first.timestamp.format(EVENT_TIME_FORMAT),
+            last.timestamp.format(EVENT_TIME_FORMAT)
+        );
+        println!("{}", times);
+        log_line(EVENT_TIMED_STREAM, &times);
+    }
+    failures.len()
+}
+
+/// `event_timed <timeout_secs> <do_period> [on_fail=continue|abort] <instruction...>`
+/// runs any instruction every `do_period` seconds until `timeout_secs` have passed.
+fn event_timed(ctx: &mut InstructionContext, trimmed_line: &str) -> Result<(), Box<dyn Error>> {
     // Split and collect all whitespace-separated tokens
     let args: Vec<&str> = trimmed_line.split_whitespace().collect();
 
     // 1. Validate we have at least 4 tokens: [program, timeout, do_period, ...command]
     if args.len() < 4 {
-        return Err("Usage: event_timed <timeout_secs> <do_period> <command...>".into());
+        return Err(
+            "Usage: event_timed <timeout_secs> <do_period> [on_fail=continue|abort] <instruction...>"
+                .into(),
+        );
     }
 
     // 2. Safely fetch and parse `timeout_secs`
@@ -36,6 +137,9 @@ fn event_timed(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
         .parse()
         .map_err(|e| format!("Invalid do_period '{}': {}", args[2], e))?;
 
+    if do_period == 0 {
+        return Err("The period must be at least 1 second".into());
+    }
     if timeout < do_period {
         return Err("Timeout cannot be less than the period".into());
     }
@@ -46,95 +150,590 @@ fn event_timed(trimmed_line: &str) -> Result<(), Box<dyn Error>> {
         cycle_cntr += 1;
     }
 
-    // 4. Everything from index 3 onward is the actual command
-    let command_line = args[3..].join(" ");
+    // 4. An optional on_fail option, everything after it is the instruction
+    let (on_fail, command_start) = match args[3].strip_prefix(ON_FAIL_OPTION) {
+        Some(value) => (OnFail::parse(value)?, 4),
+        None => (OnFail::Abort, 3),
+    };
+    if args.len() <= command_start {
+        return Err("Missing the instruction of event_timed".into());
+    }
+    let command_line = args[command_start..].join(" ");
 
-    // 5. Core timed loop
+    // 5. Core timed loop, the instruction goes through the normal dispatcher
     println!(
         "Timed event loop for: {} seconds, at {} second intervals. Event: {}",
         timeout, do_period, command_line
     );
+    let mut iterations: Vec<EventIteration> = Vec::new();
     while cycle_cntr > 0 {
         wait_s(do_period);
+        if ctx.is_cancelled() {
+            println!("Timed event '{}' cancelled, the test failed", command_line);
+            break;
+        }
         cycle_cntr -= 1;
-        match ccc_handler(&command_line, true) {
-            Ok(_) => {}
+        let result = run_instruction_line(ctx, &command_line, true);
+        let timestamp = Local::now();
+        let error = result.err().map(|e| e.to_string());
+        if let Some(e) = &error {
+            print_warn_ln!(
+                "Iteration {} failed, {} left: {}",
+                iterations.len() + 1,
+                human_readable_time(cycle_cntr * do_period),
+                e
+            );
+        }
+        let abort = error.is_some() && on_fail == OnFail::Abort;
+        iterations.push(EventIteration { timestamp, error });
+        if abort {
+            break;
+        }
+    }
+
+    let failures = event_timed_summary(&command_line, &iterations);
+    if failures > 0 {
+        let last_error = iterations
+            .iter()
+            .rev()
+            .find_map(|i| i.error.as_deref())
+            .unwrap_or_default();
+        return Err(format!(
+            "Timed event '{}' failed {} time(s), last error: {}",
+            command_line, failures, last_error
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Expected run time and number of steps waiting on the user, derived from
+/// `wait_s`, `event_timed`, `until` and `wait_e` instructions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InstructionEstimate {
+    pub duration: Duration,
+    pub manual_steps: u32,
+}
+
+fn line_estimate(line: &str) -> InstructionEstimate {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let seconds = |idx: usize| {
+        args.get(idx)
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    match args.first() {
+        Some(&"wait_s") | Some(&"event_timed") => InstructionEstimate {
+            duration: Duration::from_secs(seconds(1)),
+            manual_steps: 0,
+        },
+        Some(&POWER_KEYWORD) => InstructionEstimate {
+            duration: Duration::from_secs(power_cycle_duration(line).unwrap_or(0) as u64),
+            manual_steps: 0,
+        },
+        Some(&WAIT_SENSOR_UP_KEYWORD) => InstructionEstimate {
+            duration: wait_sensor_up_options(line)
+                .map(|(timeout, _)| timeout)
+                .unwrap_or_default(),
+            manual_steps: 0,
+        },
+        Some(&FLASH_FIRMWARE_KEYWORD) => InstructionEstimate {
+            duration: flash_firmware_duration(line),
+            manual_steps: 0,
+        },
+        Some(&UNTIL_KEYWORD) => InstructionEstimate {
+            duration: until_timeout(line),
+            manual_steps: 0,
+        },
+        Some(&"wait_e") => InstructionEstimate {
+            duration: Duration::ZERO,
+            manual_steps: 1,
+        },
+        _ => InstructionEstimate::default(),
+    }
+}
+
+impl InstructionEstimate {
+    fn add(&mut self, other: InstructionEstimate) {
+        self.duration += other.duration;
+        self.manual_steps += other.manual_steps;
+    }
+
+    fn max(self, other: InstructionEstimate) -> InstructionEstimate {
+        InstructionEstimate {
+            duration: self.duration.max(other.duration),
+            manual_steps: self.manual_steps.max(other.manual_steps),
+        }
+    }
+}
+
+/// Background blocks run alongside the other instructions and add no time,
+/// a parallel block takes as long as its longest line, an `if` block as its
+/// longest branch and a `repeat` block its count times its body. `until`
+/// lines count for their timeout.
+pub fn estimate_instructions(instructions: &[String]) -> InstructionEstimate {
+    let mut estimate = InstructionEstimate::default();
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let line = instructions[idx].trim();
+        let keyword = line.split_whitespace().next().unwrap_or_default();
+        if !BLOCK_KEYWORDS.contains(&keyword) {
+            estimate.add(line_estimate(line));
+            idx += 1;
+            continue;
+        }
+        // An unbalanced block fails when run, estimate what comes before it
+        let Ok((body, next)) = block_body(instructions, idx) else {
+            break;
+        };
+        match keyword {
+            BACKGROUND_KEYWORD => {}
+            PARALLEL_KEYWORD => {
+                let longest = body
+                    .iter()
+                    .map(|l| line_estimate(l))
+                    .fold(InstructionEstimate::default(), InstructionEstimate::max);
+                estimate.add(longest);
+            }
+            IF_KEYWORD => {
+                let (then_lines, else_lines) = split_else(body);
+                estimate
+                    .add(estimate_instructions(then_lines).max(estimate_instructions(else_lines)));
+            }
+            _ => {
+                let count = repeat_count(line).unwrap_or(0);
+                let once = estimate_instructions(body);
+                estimate.add(InstructionEstimate {
+                    duration: once.duration * count,
+                    manual_steps: once.manual_steps * count,
+                });
+            }
+        }
+        idx = next;
+    }
+    estimate
+}
+
+/// A block of instructions running in its own thread.
+struct BackgroundTask {
+    label: String,
+    handle: JoinHandle<Result<(), String>>,
+}
+
+/// Join `tasks` and print their results. Fails if any of them failed.
+fn join_tasks(kind: &str, tasks: Vec<BackgroundTask>) -> Result<(), Box<dyn Error>> {
+    let total = tasks.len();
+    let mut errors: Vec<String> = Vec::new();
+    for task in tasks {
+        let result = task
+            .handle
+            .join()
+            .unwrap_or_else(|_| Err("the task panicked".to_string()));
+        match result {
+            Ok(()) => println!("{} task '{}' succeeded", kind, task.label),
             Err(e) => {
-                print_warn_ln!("Failed in {}", human_readable_time(cycle_cntr * do_period));
-                return Err(e);
+                print_warn_ln!("{} task '{}' failed: {}", kind, task.label, e);
+                errors.push(format!("'{}': {}", task.label, e));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format!(
+            "{} of {} {} task(s) failed: {}",
+            errors.len(),
+            total,
+            kind.to_lowercase(),
+            errors.join("; ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The lines between the block opened at `start` and its matching `end`, and
+/// the index of the line after that `end`.
+fn block_body(instructions: &[String], start: usize) -> Result<(&[String], usize), Box<dyn Error>> {
+    let mut depth = 0;
+    for (idx, line) in instructions.iter().enumerate().skip(start + 1) {
+        let keyword = line.split_whitespace().next().unwrap_or_default();
+        if BLOCK_KEYWORDS.contains(&keyword) {
+            depth += 1;
+        } else if keyword == END_KEYWORD {
+            if depth == 0 {
+                return Ok((&instructions[start + 1..idx], idx + 1));
             }
+            depth -= 1;
+        }
+    }
+    Err(format!(
+        "'{}' block without a matching '{}'",
+        instructions[start].trim(),
+        END_KEYWORD
+    )
+    .into())
+}
+
+/// Split the body of an `if` block at its `else`, nested blocks keep theirs.
+fn split_else(body: &[String]) -> (&[String], &[String]) {
+    let mut depth = 0;
+    for (idx, line) in body.iter().enumerate() {
+        let keyword = line.split_whitespace().next().unwrap_or_default();
+        if BLOCK_KEYWORDS.contains(&keyword) {
+            depth += 1;
+        } else if keyword == END_KEYWORD {
+            depth -= 1;
+        } else if keyword == ELSE_KEYWORD && depth == 0 {
+            return (&body[..idx], &body[idx + 1..]);
+        }
+    }
+    (body, &[])
+}
+
+/// State shared by the instructions of one test run or group hook.
+struct InstructionContext {
+    artifacts: TestArtifacts,
+    auto: bool,
+    // Owned by this run, the server is stopped when the run ends.
+    dhcp_server: Option<DhcpServerHandle>,
+    // Background blocks started by this run, joined at its end.
+    background: Vec<BackgroundTask>,
+    variables: Variables,
+    // Cancel flags of this run and of the runs that started it. The blocks
+    // running in other threads stop at their next step once one is set.
+    cancel_flags: Vec<Arc<AtomicBool>>,
+    is_block_thread: bool,
+}
+
+impl InstructionContext {
+    fn new(artifacts: &TestArtifacts, auto: bool) -> Self {
+        InstructionContext {
+            artifacts: artifacts.clone(),
+            auto,
+            dhcp_server: None,
+            background: Vec::new(),
+            variables: Variables::new(),
+            cancel_flags: vec![Arc::new(AtomicBool::new(false))],
+            is_block_thread: false,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.is_block_thread && self.cancel_flags.iter().any(|f| f.load(Ordering::Acquire))
+    }
+
+    /// Stop the background blocks started by this run.
+    fn cancel_background(&self) {
+        if let Some(flag) = self.cancel_flags.last() {
+            flag.store(true, Ordering::Release);
+        }
+    }
+
+    /// Run `lines` in a new thread. Blocks run automatically, so they cannot
+    /// wait on the user.
+    fn spawn(&self, label: &str, lines: &[String]) -> Result<BackgroundTask, Box<dyn Error>> {
+        if let Some(line) = lines.iter().find(|l| l.trim().starts_with("wait_e")) {
+            return Err(format!("'{}' cannot wait on the user in a block: {}", label, line).into());
+        }
+        let mut block_ctx = InstructionContext {
+            artifacts: self.artifacts.clone(),
+            auto: true,
+            dhcp_server: None,
+            background: Vec::new(),
+            variables: self.variables.clone(),
+            cancel_flags: self
+                .cancel_flags
+                .iter()
+                .cloned()
+                .chain([Arc::new(AtomicBool::new(false))])
+                .collect(),
+            is_block_thread: true,
         };
+        let lines = lines.to_vec();
+        let handle = thread::spawn(move || {
+            run_instruction_block(&mut block_ctx, &lines).map_err(|e| e.to_string())
+        });
+        Ok(BackgroundTask {
+            label: label.to_string(),
+            handle,
+        })
+    }
+
+    /// Join the background blocks started so far.
+    fn join_background(&mut self) -> Result<(), Box<dyn Error>> {
+        let tasks = std::mem::take(&mut self.background);
+        if tasks.is_empty() {
+            return Ok(());
+        }
+        println!("Waiting for {} background task(s)...", tasks.len());
+        join_tasks("Background", tasks)
+    }
+}
+
+impl Drop for InstructionContext {
+    fn drop(&mut self) {
+        if !self.background.is_empty() {
+            self.cancel_background();
+            let _ = self.join_background();
+        }
+        if let Some(mut server) = self.dhcp_server.take() {
+            server.stop();
+        }
     }
+}
 
+/// Run each line of a parallel block in its own thread and wait for all of them.
+fn run_parallel(ctx: &InstructionContext, lines: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut tasks = Vec::new();
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') {
+            println!("  - {}", trimmed);
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed
+            .split_whitespace()
+            .next()
+            .is_some_and(|k| BLOCK_KEYWORDS.contains(&k))
+        {
+            return Err(format!("Blocks cannot be nested in a parallel block: {}", trimmed).into());
+        }
+        tasks.push(ctx.spawn(trimmed, std::slice::from_ref(line))?);
+    }
+    println!("Running {} instruction(s) in parallel", tasks.len());
+    join_tasks("Parallel", tasks)
+}
+
+/// Dispatch one instruction line to its handler. `auto` skips the prompts of
+/// semi automatic steps.
+fn run_instruction_line(
+    ctx: &mut InstructionContext,
+    trimmed: &str,
+    auto: bool,
+) -> Result<(), Box<dyn Error>> {
+    let first_word = trimmed.split_whitespace().next().unwrap_or_default();
+    if trimmed.starts_with("##") || trimmed.starts_with("#") {
+        println!("  - {}", trimmed);
+    } else if trimmed.starts_with(INCLUDE_KEYWORD) {
+        return Err(format!(
+            "Unexpanded snippet '{}', regenerate the instruction file from the base test list",
+            trimmed
+        )
+        .into());
+    } else if first_word == LET_KEYWORD {
+        let_handler(trimmed, &mut ctx.variables)?;
+    } else if first_word == UNTIL_KEYWORD {
+        until_handler(trimmed, &ctx.variables)?;
+    } else if first_word == ASSERT_KEYWORD {
+        assert_handler(trimmed)?;
+    } else if first_word == RESTORE_BASELINE_KEYWORD {
+        restore_baseline(&ctx.artifacts, trimmed, auto)?;
+    } else if first_word == APPLY_PROFILE_KEYWORD {
+        apply_profile(trimmed, auto)?;
+    } else if first_word == WAIT_SENSOR_UP_KEYWORD {
+        wait_until_sensor_up(trimmed)?;
+    } else if first_word == FLASH_FIRMWARE_KEYWORD {
+        flash_firmware(&ctx.artifacts, trimmed, auto)?;
+    } else if first_word == POWER_KEYWORD {
+        power_handler(trimmed, auto, ctx.is_block_thread)?;
+    } else if trimmed.starts_with("ccc") {
+        ccc_handler(trimmed, auto)?;
+    } else if trimmed.starts_with("event_timed") {
+        event_timed(ctx, trimmed)?;
+    } else if trimmed.starts_with("factory_init") {
+        factory_init(trimmed)?;
+    } else if trimmed.starts_with("dhcp_") {
+        dhcp_handler(&ctx.artifacts, trimmed, &mut ctx.dhcp_server)?;
+    } else if trimmed.starts_with("panorama") {
+        panorama_cli_handler(trimmed)?;
+    } else {
+        generic_runner(trimmed)?;
+    }
     Ok(())
 }
 
+fn run_instruction_lines(
+    ctx: &mut InstructionContext,
+    instructions: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let mut idx = 0;
+    while idx < instructions.len() {
+        if ctx.is_cancelled() {
+            return Err("Cancelled, the test failed".into());
+        }
+        // Comments are shown as written and `until` substitutes on every poll
+        let line = instructions[idx].trim();
+        let substituted =
+            if line.starts_with('#') || line.split_whitespace().next() == Some(UNTIL_KEYWORD) {
+                line.to_string()
+            } else {
+                substitute_variables(line, &ctx.variables)?
+            };
+        let trimmed = substituted.as_str();
+        match trimmed.split_whitespace().next().unwrap_or_default() {
+            BACKGROUND_KEYWORD => {
+                let (lines, next) = block_body(instructions, idx)?;
+                let label = format!("background block at line {}", idx + 1);
+                println!("Starting {}", label);
+                let task = ctx.spawn(&label, lines)?;
+                ctx.background.push(task);
+                idx = next;
+            }
+            PARALLEL_KEYWORD => {
+                let (lines, next) = block_body(instructions, idx)?;
+                run_parallel(ctx, lines)?;
+                idx = next;
+            }
+            IF_KEYWORD => {
+                let (body, next) = block_body(instructions, idx)?;
+                let (then_lines, else_lines) = split_else(body);
+                if if_condition(trimmed)? {
+                    run_instruction_lines(ctx, then_lines)?;
+                } else {
+                    run_instruction_lines(ctx, else_lines)?;
+                }
+                idx = next;
+            }
+            REPEAT_KEYWORD => {
+                let (body, next) = block_body(instructions, idx)?;
+                let count = repeat_count(trimmed)?;
+                for iteration in 1..=count {
+                    println!("  - Repeat {}/{}", iteration, count);
+                    run_instruction_lines(ctx, body)?;
+                }
+                idx = next;
+            }
+            keyword @ (END_KEYWORD | ELSE_KEYWORD) => {
+                return Err(format!("'{}' without an open block", keyword).into());
+            }
+            _ => {
+                let auto = ctx.auto;
+                run_instruction_line(ctx, trimmed, auto)?;
+                idx += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run `instructions` and then join the background blocks they started.
+/// Background blocks are cancelled if an instruction failed.
+fn run_instruction_block(
+    ctx: &mut InstructionContext,
+    instructions: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let result = run_instruction_lines(ctx, instructions);
+    if result.is_err() {
+        ctx.cancel_background();
+    }
+    let joined = ctx.join_background();
+    result.and(joined)
+}
+
 fn instruction_handler(
-    test_id: &str,
-    instructions: &Vec<Value>,
+    artifacts: &TestArtifacts,
+    hooks: &TestHooks,
+    instructions: &[String],
     auto: bool,
 ) -> Result<(), Box<dyn Error>> {
-    let mut pcap_instance = PcapInstance::new(test_id);
+    let mut pcap_instance = PcapInstance::new(artifacts);
     pcap_instance.start();
-    for instr in instructions {
-        if let Some(line) = instr.as_str() {
-            let trimmed = line.trim();
-
-            if trimmed.starts_with("##") || trimmed.starts_with("#") {
-                println!("  - {}", trimmed);
-            } else if trimmed.starts_with("ccc") {
-                ccc_handler(trimmed, auto)?;
-            } else if trimmed.starts_with("event_timed") {
-                event_timed(trimmed)?;
-            } else if trimmed.starts_with("factory_init") {
-                factory_init()?;
-            } else if trimmed.starts_with("panorama") {
-                panorama_cli_handler(trimmed)?;
-            } else {
-                generic_runner(trimmed)?;
+    let mut ctx = InstructionContext::new(artifacts, auto);
+
+    let mut result = Ok(());
+    if !hooks.setup.is_empty() {
+        println!("  Setup:");
+        result = run_instruction_lines(&mut ctx, hooks.setup);
+    }
+    if result.is_ok() {
+        result = run_instruction_lines(&mut ctx, instructions);
+    }
+    // Background blocks of the setup and instructions end before the teardown
+    if result.is_err() {
+        ctx.cancel_background();
+    }
+    if let Err(e) = ctx.join_background()
+        && result.is_ok()
+    {
+        result = Err(e);
+    }
+    // Background blocks of the teardown must not see the cancellation
+    ctx.cancel_flags = vec![Arc::new(AtomicBool::new(false))];
+    // Teardown always runs, a teardown failure only surfaces if nothing failed before.
+    if !hooks.teardown.is_empty() {
+        snapshot_config(artifacts, SnapshotStage::BeforeTeardown);
+        println!("  Teardown:");
+        if let Err(e) = run_instruction_lines(&mut ctx, hooks.teardown) {
+            print_warn_ln!("Teardown failed: {}", e);
+            if result.is_ok() {
+                result = Err(e);
             }
         }
     }
+
+    drop(ctx);
     pcap_instance.stop();
-    Ok(())
+    result
+}
+
+/// Setup and teardown instructions wrapped around the instructions of a test.
+#[derive(Default)]
+pub struct TestHooks<'a> {
+    pub setup: &'a [String],
+    pub teardown: &'a [String],
 }
 
+/// Runs group level setup or teardown instructions automatically.
+pub fn group_hooks_runner(
+    artifacts: &TestArtifacts,
+    instructions: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let mut ctx = InstructionContext::new(artifacts, true);
+    run_instruction_block(&mut ctx, instructions)
+}
+
+/// How the automated steps of a test went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomationResult {
+    /// They succeeded, or the test has none.
+    Passed,
+    Failed,
+    /// The technician declined to run them.
+    Skipped,
+}
+
+/// Runs the automated steps of a test. Unless `unattended`, the technician is
+/// asked first and may skip them.
 pub fn instructions_runner(
-    test_id: &str,
+    artifacts: &TestArtifacts,
     command: &str,
-    instructions: &Vec<Value>,
-) -> Result<(), Box<dyn Error>> {
-    match command {
-        "SEMI_AUTO" => {
-            println!("\nSEMI_AUTO detected.");
-            if get_key_entry_y()? == 0 {
-                println!("Skipping automatic steps.");
-                return Ok(());
-            }
-            print_thin_separator();
-            println!("Step by step semi automatic instruction runner");
-            if let Err(e) = instruction_handler(test_id, instructions, false) {
-                eprintln!("Error in semi-automatic command handler: {}", e);
-            }
-        }
-        "FULL_AUTO" => {
-            println!("\nFULL_AUTO detected.");
-            if get_key_entry_y()? == 0 {
-                println!("Skipping automatic steps.");
-                return Ok(());
-            }
-            print_thin_separator();
-            println!("Automatic instruction runner");
-            if let Err(e) = instruction_handler(test_id, instructions, true) {
-                eprintln!("Error in full-automatic command handler: {}", e);
-            }
-        }
+    hooks: &TestHooks,
+    instructions: &[String],
+    unattended: bool,
+) -> Result<AutomationResult, Box<dyn Error>> {
+    let (auto, title) = match command {
+        "SEMI_AUTO" => (false, "Step by step semi automatic instruction runner"),
+        "FULL_AUTO" => (true, "Automatic instruction runner"),
         _ => {
             println!("No auto commands found in instructions.");
+            return Ok(AutomationResult::Passed);
         }
-    }
+    };
 
-    Ok(())
+    println!("\n{} detected.", command);
+    if !unattended && get_key_entry_y()? == 0 {
+        println!("Skipping automatic steps.");
+        return Ok(AutomationResult::Skipped);
+    }
+    print_thin_separator();
+    println!("{}", title);
+    if let Err(e) = instruction_handler(artifacts, hooks, instructions, auto) {
+        eprintln!("Error in {} command handler: {}", command, e);
+        return Ok(AutomationResult::Failed);
+    }
+    Ok(AutomationResult::Passed)
 }
 
 pub fn get_instruction_type(line: &str) -> Result<Option<&'static str>, Box<dyn Error>> {
@@ -158,3 +757,136 @@ pub fn get_instruction_type(line: &str) -> Result<Option<&'static str>, Box<dyn
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|l| l.to_string()).collect()
+    }
+
+    fn artifacts() -> TestArtifacts {
+        let dir = std::env::temp_dir().join("vtg_instructions_test");
+        TestArtifacts {
+            dir: dir.clone(),
+            config_dir: dir,
+            name: "test".to_string(),
+            firmware: None,
+            report: None,
+        }
+    }
+
+    #[test]
+    fn comments_are_not_substituted() {
+        let mut ctx = InstructionContext::new(&artifacts(), true);
+        let instructions = lines(&[
+            "## Costs $5, see $docs ##",
+            "let x = 3",
+            "assert $x == 3",
+            "if $x > 2",
+            "## $x is above 2 ##",
+            "let y = $x",
+            "end",
+            "assert $y == 3",
+        ]);
+        run_instruction_lines(&mut ctx, &instructions).unwrap();
+
+        assert!(run_instruction_lines(&mut ctx, &lines(&["assert $docs == 1"])).is_err());
+    }
+
+    #[test]
+    fn block_body_skips_nested_blocks() {
+        let instructions = lines(&[
+            "background",
+            "if 1 == 1",
+            "wait_s 1",
+            "end",
+            "repeat 2",
+            "wait_s 2",
+            "end",
+            "end",
+            "wait_s 3",
+        ]);
+        let (body, next) = block_body(&instructions, 0).unwrap();
+        assert_eq!(body, &instructions[1..7]);
+        assert_eq!(next, 8);
+
+        let (body, next) = block_body(&instructions, 1).unwrap();
+        assert_eq!(body, &instructions[2..3]);
+        assert_eq!(next, 4);
+    }
+
+    #[test]
+    fn unbalanced_block_is_rejected() {
+        let instructions = lines(&["parallel", "if 1 == 1", "wait_s 1", "end"]);
+        let error = block_body(&instructions, 0).unwrap_err().to_string();
+        assert!(error.contains("'parallel' block"), "{}", error);
+    }
+
+    #[test]
+    fn else_of_nested_blocks_is_kept() {
+        let body = lines(&[
+            "wait_s 1",
+            "if 2 == 2",
+            "wait_s 2",
+            "else",
+            "wait_s 3",
+            "end",
+            "else",
+            "wait_s 4",
+        ]);
+        let (then_lines, else_lines) = split_else(&body);
+        assert_eq!(then_lines, &body[..6]);
+        assert_eq!(else_lines, &body[7..]);
+
+        let (then_lines, else_lines) = split_else(&body[..6]);
+        assert_eq!(then_lines, &body[..6]);
+        assert!(else_lines.is_empty());
+    }
+
+    #[test]
+    fn estimate_sums_waits_and_manual_steps() {
+        let estimate = estimate_instructions(&lines(&[
+            "## FULL_AUTO ##",
+            "wait_s 60",
+            "event_timed 120 10 ccc list-sensors",
+            "wait_e",
+            "until ccc get pixel_format == 3 timeout=30s",
+            "ccc get-all",
+        ]));
+        assert_eq!(estimate.duration, Duration::from_secs(210));
+        assert_eq!(estimate.manual_steps, 1);
+    }
+
+    #[test]
+    fn estimate_of_blocks() {
+        let estimate = estimate_instructions(&lines(&[
+            "background",
+            "wait_s 1000",
+            "end",
+            "parallel",
+            "wait_s 10",
+            "wait_s 40",
+            "end",
+            "if 1 == 1",
+            "wait_s 5",
+            "else",
+            "wait_s 20",
+            "wait_e",
+            "end",
+            "repeat 3",
+            "wait_s 2",
+            "wait_e",
+            "end",
+        ]));
+        assert_eq!(estimate.duration, Duration::from_secs(40 + 20 + 6));
+        assert_eq!(estimate.manual_steps, 1 + 3);
+    }
+
+    #[test]
+    fn estimate_stops_at_an_unbalanced_block() {
+        let estimate = estimate_instructions(&lines(&["wait_s 7", "repeat 2", "wait_s 100"]));
+        assert_eq!(estimate.duration, Duration::from_secs(7));
+    }
+}
@@ -1,124 +1,123 @@
 use std::error::Error;
-use std::fs;
-use toml::Value;
+use std::time::Duration;
 
-use crate::test_ops::test_ops_process_instructions::{get_instruction_type, instructions_runner};
+use crate::files_ops::campaign_ops::TestArtifacts;
+use crate::files_ops::instruction_file::InstructionFile;
+use crate::supporters::misc::human_readable_time;
+use crate::test_ops::test_ops_process_instructions::{
+    AutomationResult, TestHooks, get_instruction_type, instructions_runner,
+};
 
+fn print_hook_lines(title: &str, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+    println!("{}:", title);
+    for line in lines {
+        println!("  - {}", line);
+    }
+}
+
+/// Prints the instructions of a test and runs its automated steps, without
+/// asking first if `unattended`.
 pub fn process_fetched_instructions(
-    test_id: &str,
-    instructions: &Vec<Value>,
-) -> Result<(), Box<dyn Error>> {
+    artifacts: &TestArtifacts,
+    hooks: &TestHooks,
+    instructions: &[String],
+    unattended: bool,
+) -> Result<AutomationResult, Box<dyn Error>> {
     let mut auto_command: Option<&'static str> = None;
 
-    for instr in instructions {
-        if let Some(line) = instr.as_str() {
-            println!("  - {}", line);
+    print_hook_lines("Setup", hooks.setup);
+    println!("Instructions:");
+
+    for line in instructions {
+        println!("  - {}", line);
 
-            if auto_command.is_none() {
-                match get_instruction_type(line)? {
-                    Some(cmd) => auto_command = Some(cmd),
-                    None => {
-                        // No auto command found in this line; continue
-                    }
+        if auto_command.is_none() {
+            match get_instruction_type(line)? {
+                Some(cmd) => auto_command = Some(cmd),
+                None => {
+                    // No auto command found in this line; continue
                 }
             }
         }
     }
 
+    print_hook_lines("Teardown", hooks.teardown);
+
     if let Some(cmd) = auto_command {
-        instructions_runner(test_id, cmd, instructions)?;
+        return instructions_runner(artifacts, cmd, hooks, instructions, unattended);
     }
 
-    Ok(())
+    Ok(AutomationResult::Passed)
 }
 
-pub fn ar_process_test_item(file: &str, user_input_test_id: &str) -> Result<(), Box<dyn Error>> {
-    let content = fs::read_to_string(file)?;
-    let parsed: Value = content.parse::<Value>()?;
-
-    for (group_name, group_value) in parsed.as_table().ok_or("Invalid TOML structure")? {
-        if let Some(tests) = group_value.get("test").and_then(|v| v.as_array()) {
-            for test in tests {
-                let test_id = test.get("test_id").and_then(|v| v.as_str()).unwrap_or("");
-                if test_id == user_input_test_id {
-                    println!("Test Group: {}", group_name);
-                    println!("Test ID: {}", test_id);
-                    let pass_condition = test
-                        .get("pass_condition")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-                    println!("Pass Condition: {}", pass_condition);
-                    println!("Instructions:");
-                    if let Some(instructions) = test.get("instructions").and_then(|v| v.as_array())
-                    {
-                        process_fetched_instructions(test_id, instructions)?;
-                    }
-                    return Ok(());
-                }
-            }
+/// Runs the test `user_input_test_id` of `file`, without asking before its
+/// automated steps if `unattended`.
+pub fn ar_process_test_item(
+    file: &InstructionFile,
+    user_input_test_id: &str,
+    artifacts: &TestArtifacts,
+    unattended: bool,
+) -> Result<AutomationResult, Box<dyn Error>> {
+    match file.find_test(user_input_test_id) {
+        Some((group_name, test)) => {
+            println!("Test Group: {}", group_name);
+            println!("Test ID: {}", test.test_id);
+            println!("Pass Condition: {}", test.pass_condition);
+            let hooks = TestHooks {
+                setup: &test.setup,
+                teardown: &test.teardown,
+            };
+            process_fetched_instructions(artifacts, &hooks, &test.instructions, unattended)
+        }
+        None => {
+            println!(
+                "Test ID '{}' not found in instruction file",
+                user_input_test_id
+            );
+            Ok(AutomationResult::Passed)
         }
     }
-
-    println!(
-        "Test ID '{}' not found in file '{}'",
-        user_input_test_id, file
-    );
-
-    Ok(())
 }
 
-pub fn ar_print_test_item(file: &str, user_input_test_id: &str) -> Result<(), Box<dyn Error>> {
-    // Read and parse
-    let content = fs::read_to_string(file)?;
-    let parsed: Value = content.parse::<Value>()?;
-    let table = parsed
-        .as_table()
-        .ok_or("Invalid TOML structure: expected a table at top level")?;
-
-    // First pass: compute max widths
+/// Prints one row of the test listing and returns the estimated duration and
+/// manual step count of the test, `None` if it is not in the file.
+pub fn ar_print_test_item(
+    file: &InstructionFile,
+    user_input_test_id: &str,
+) -> Result<Option<(Duration, u32)>, Box<dyn Error>> {
+    // Compute column widths over the whole file so every row lines up
     let mut max_group_len = 0;
     let mut max_test_id_len = 0;
-    for (group_name, group_value) in table {
-        if let Some(tests) = group_value.get("test").and_then(|v| v.as_array()) {
-            for test in tests {
-                if let Some(id) = test.get("test_id").and_then(|v| v.as_str()) {
-                    max_group_len = max_group_len.max(group_name.len());
-                    max_test_id_len = max_test_id_len.max(id.len());
-                }
-            }
-        }
+    for (group_name, test) in file.tests() {
+        max_group_len = max_group_len.max(group_name.len());
+        max_test_id_len = max_test_id_len.max(test.test_id.len());
     }
     // Add padding
     let col1_width = max_group_len + 2;
     let col2_width = max_test_id_len + 2;
 
-    // Second pass: find and print the matching test
-    for (group_name, group_value) in table {
-        if let Some(tests) = group_value.get("test").and_then(|v| v.as_array()) {
-            for test in tests {
-                let test_id = test.get("test_id").and_then(|v| v.as_str()).unwrap_or("");
-                if test_id == user_input_test_id {
-                    let test_description = test
-                        .get("test_description")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-                    println!(
-                        "{:<col1$}{:<col2$}{}",
-                        group_name,
-                        test_id,
-                        test_description,
-                        col1 = col1_width,
-                        col2 = col2_width
-                    );
-                    return Ok(());
-                }
-            }
-        }
-    }
+    let Some((group_name, test)) = file.find_test(user_input_test_id) else {
+        println!(
+            "Test ID '{}' not found in instruction file",
+            user_input_test_id
+        );
+        return Ok(None);
+    };
 
+    let duration = test.estimated_duration()?;
+    let manual_steps = test.instruction_estimate().manual_steps;
     println!(
-        "Test ID '{}' not found in file '{}'",
-        user_input_test_id, file
+        "{:<col1$}{:<col2$}{:<14}{:<11}{}",
+        group_name,
+        test.test_id,
+        format!("~{}", human_readable_time(duration.as_secs() as u32)),
+        format!("manual: {}", manual_steps),
+        test.test_description,
+        col1 = col1_width,
+        col2 = col2_width
     );
-    Ok(())
+    Ok(Some((duration, manual_steps)))
 }
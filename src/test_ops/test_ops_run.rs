@@ -0,0 +1,427 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::files_ops::campaign_ops::{AttemptRecord, Campaign};
+use crate::files_ops::excel_ops::{
+    report_sheet_test_id_blocked, report_sheet_test_id_fail, report_sheet_test_id_not_verified,
+    report_sheet_test_id_pass, report_sheet_test_id_skipped,
+};
+use crate::files_ops::file_ops::test_id_matches;
+use crate::files_ops::instruction_file::InstructionFile;
+use crate::supporters::log_ops::{start_test_log, stop_test_log};
+use crate::supporters::misc::{
+    human_readable_time, print_thick_separator, print_thin_separator, test_verdict_prompt,
+};
+use crate::test_ops::test_ops_config_snapshots::{
+    SnapshotStage, check_config_snapshots, snapshot_config,
+};
+use crate::test_ops::test_ops_process_instructions::{AutomationResult, group_hooks_runner};
+use crate::test_ops::test_ops_process_tests::ar_process_test_item;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+    Blocked,
+    /// The automated instructions succeeded but no technician gave a verdict.
+    NotVerified,
+    /// The technician declined to run the automated instructions.
+    Skipped,
+}
+
+impl TestOutcome {
+    /// Name of the outcome in the report Status column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestOutcome::Passed => "Pass",
+            TestOutcome::Failed => "Fail",
+            TestOutcome::Blocked => "Blocked",
+            TestOutcome::NotVerified => "Not verified",
+            TestOutcome::Skipped => "Skipped",
+        }
+    }
+
+    /// Whether the test ran, Blocked and Skipped attempts are left out of the
+    /// pass counts.
+    pub fn ran(outcome: &str) -> bool {
+        outcome != TestOutcome::Blocked.as_str() && outcome != TestOutcome::Skipped.as_str()
+    }
+}
+
+/// How `run_test_ids` runs the tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestRunMode {
+    /// Ask the technician for the verdict of each test when a report is
+    /// updated.
+    pub prompt_verdict: bool,
+    /// Run the automated steps without asking first.
+    pub unattended: bool,
+    /// Stop at the first failed test, after the teardown of its group.
+    pub stop_on_fail: bool,
+}
+
+/// Outcomes of `prerequisite`, or of all its variants if it names a matrix test.
+fn prerequisite_outcomes<'a>(
+    outcomes: &'a HashMap<String, TestOutcome>,
+    prerequisite: &'a str,
+) -> impl Iterator<Item = TestOutcome> + 'a {
+    outcomes
+        .iter()
+        .filter(move |(id, _)| test_id_matches(prerequisite, id))
+        .map(|(_, outcome)| *outcome)
+}
+
+fn run_group_hook(
+    campaign: &Campaign,
+    test_group: &str,
+    kind: &str,
+    lines: &[String],
+    report: Option<&Path>,
+) -> bool {
+    if lines.is_empty() {
+        return true;
+    }
+    print_thick_separator();
+    println!("Group {} for: {}", kind, test_group);
+    match group_hooks_runner(&campaign.group_artifacts(test_group, report), lines) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Group {} for '{}' failed: {}", kind, test_group, e);
+            false
+        }
+    }
+}
+
+/// Print the earlier attempts of a test, so a rerun shows how it went before.
+fn print_attempt_history(test_id: &str, attempts: &[AttemptRecord]) {
+    if attempts.is_empty() {
+        return;
+    }
+    println!("Previous attempts of test '{}':", test_id);
+    for a in attempts {
+        println!(
+            "  #{} {} [{}] {}",
+            a.attempt, a.outcome, a.timestamp, a.notes
+        );
+        if !a.artifacts.is_empty() {
+            println!("     {}", a.artifacts);
+        }
+    }
+}
+
+/// Runs `ids` in order. Group setup runs before the first test of a group and
+/// group teardown after its last one. A test whose prerequisite failed or was
+/// blocked is marked Blocked without running. Every run is recorded as a new
+/// attempt of the test in `campaign`, with its verdict, notes, pcap and log,
+/// and the report Frequency column counts the passed attempts. A test whose
+/// automated instructions succeeded is Not verified, and one whose automated
+/// instructions were declined is Skipped, unless the technician gives a
+/// verdict, which is asked only with `mode.prompt_verdict` and a report.
+pub fn run_test_ids(
+    campaign: &Campaign,
+    file_path: &str,
+    ids: &[String],
+    excel_path: Option<&str>,
+    mode: TestRunMode,
+) -> Result<HashMap<String, TestOutcome>, Box<dyn Error>> {
+    let instruction_file = InstructionFile::load(file_path)?;
+
+    // Run metadata of each test: its `test_group` and the IDs listed in `requires`
+    let dependencies: Vec<(String, Vec<String>)> = ids
+        .iter()
+        .map(|id| {
+            instruction_file
+                .find_test(id)
+                .map(|(_, t)| (t.test_group.clone(), t.requires.clone()))
+                .unwrap_or_default()
+        })
+        .collect();
+    let hook_lines = |test_group: &str, teardown: bool| -> &[String] {
+        match instruction_file.group_hooks.get(test_group) {
+            Some(hooks) if teardown => &hooks.teardown,
+            Some(hooks) => &hooks.setup,
+            None => &[],
+        }
+    };
+
+    let report = excel_path.map(Path::new);
+    let mut outcomes: HashMap<String, TestOutcome> = HashMap::new();
+    let mut failed_group_setups: HashSet<&str> = HashSet::new();
+
+    for (idx, test_id) in ids.iter().enumerate() {
+        let (test_group, requires) = &dependencies[idx];
+        let first_of_group = dependencies[..idx].iter().all(|(g, _)| g != test_group);
+        let last_of_group = dependencies[idx + 1..].iter().all(|(g, _)| g != test_group);
+
+        if first_of_group
+            && !run_group_hook(
+                campaign,
+                test_group,
+                "setup",
+                hook_lines(test_group, false),
+                report,
+            )
+        {
+            failed_group_setups.insert(test_group);
+        }
+
+        print_thick_separator();
+        for prerequisite in requires {
+            if prerequisite_outcomes(&outcomes, prerequisite)
+                .next()
+                .is_none()
+            {
+                print_warn_ln!(
+                    "Prerequisite '{}' of test '{}' has not run before it.",
+                    prerequisite,
+                    test_id
+                );
+            }
+        }
+        let blocked_reason = if failed_group_setups.contains(test_group.as_str()) {
+            Some(format!("Blocked: group setup for '{}' failed", test_group))
+        } else {
+            requires
+                .iter()
+                .find(|r| {
+                    prerequisite_outcomes(&outcomes, r)
+                        .any(|o| matches!(o, TestOutcome::Failed | TestOutcome::Blocked))
+                })
+                .map(|r| format!("Blocked: prerequisite test '{}' did not pass", r))
+        };
+
+        let previous_attempts = campaign.attempts(test_id)?;
+        print_attempt_history(test_id, &previous_attempts);
+        let attempt = previous_attempts.len() as u32 + 1;
+        let artifacts = campaign.test_artifacts(test_id, attempt, report);
+        let mut log_path = None;
+        let mut config_note = None;
+
+        let (outcome, notes) = match blocked_reason {
+            Some(reason) => {
+                println!("Test '{}' skipped. {}", test_id, reason);
+                (TestOutcome::Blocked, reason)
+            }
+            None => {
+                println!("Attempt {} of test '{}'", attempt, test_id);
+                let path = campaign.test_log_path(&artifacts);
+                match start_test_log(&path, test_id) {
+                    Ok(()) => log_path = Some(path),
+                    Err(e) => {
+                        print_warn_ln!("Failed to open the log of test '{}': {}", test_id, e);
+                    }
+                }
+                snapshot_config(&artifacts, SnapshotStage::Before);
+                let automation = match ar_process_test_item(
+                    &instruction_file,
+                    test_id,
+                    &artifacts,
+                    mode.unattended,
+                ) {
+                    Ok(automation) => automation,
+                    Err(e) => {
+                        eprintln!("Error processing test '{}': {}", test_id, e);
+                        AutomationResult::Failed
+                    }
+                };
+                snapshot_config(&artifacts, SnapshotStage::After);
+                config_note = check_config_snapshots(&artifacts);
+                stop_test_log();
+                match (automation, excel_path) {
+                    (AutomationResult::Failed, _) => (
+                        TestOutcome::Failed,
+                        "Automated instructions failed".to_string(),
+                    ),
+                    (_, Some(_)) if mode.prompt_verdict => match test_verdict_prompt()? {
+                        (true, note) => (TestOutcome::Passed, note),
+                        (false, note) => (TestOutcome::Failed, note),
+                    },
+                    (AutomationResult::Skipped, _) => (
+                        TestOutcome::Skipped,
+                        "Automated instructions skipped".to_string(),
+                    ),
+                    (AutomationResult::Passed, _) => (
+                        TestOutcome::NotVerified,
+                        "Automated instructions passed, verdict not given".to_string(),
+                    ),
+                }
+            }
+        };
+        let notes = match config_note {
+            Some(note) if notes.is_empty() => note,
+            Some(note) => format!("{}. {}", notes, note),
+            None => notes,
+        };
+
+        // Record the attempt first so the report frequency counts it
+        let mut attempt_files = artifacts.existing()?;
+        attempt_files.extend(log_path.clone());
+        campaign.record_attempt(test_id, attempt, outcome.as_str(), &notes, &attempt_files)?;
+        if let Some(xlsx) = excel_path {
+            let frequency = campaign.frequency(test_id)?;
+            let (notes, log_path, frequency) = (
+                Some(notes.as_str()),
+                log_path.as_deref(),
+                frequency.as_deref(),
+            );
+            match outcome {
+                TestOutcome::Passed => {
+                    report_sheet_test_id_pass(xlsx, test_id, notes, log_path, frequency)?
+                }
+                TestOutcome::Failed => {
+                    report_sheet_test_id_fail(xlsx, test_id, notes, log_path, frequency)?
+                }
+                TestOutcome::Blocked => {
+                    report_sheet_test_id_blocked(xlsx, test_id, notes, log_path, frequency)?
+                }
+                TestOutcome::NotVerified => {
+                    report_sheet_test_id_not_verified(xlsx, test_id, notes, log_path, frequency)?
+                }
+                TestOutcome::Skipped => {
+                    report_sheet_test_id_skipped(xlsx, test_id, notes, log_path, frequency)?
+                }
+            }
+        }
+        outcomes.insert(test_id.clone(), outcome);
+
+        let stop = mode.stop_on_fail && outcome == TestOutcome::Failed;
+        if last_of_group || stop {
+            run_group_hook(
+                campaign,
+                test_group,
+                "teardown",
+                hook_lines(test_group, true),
+                report,
+            );
+        }
+        if stop {
+            println!("Stopping: test '{}' failed", test_id);
+            break;
+        }
+    }
+
+    Ok(outcomes)
+}
+
+/// When to stop running the selected tests again. Both limits may be set, the
+/// first one reached stops the run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RepeatOptions {
+    pub iterations: Option<u32>,
+    pub duration: Option<Duration>,
+    pub stop_on_fail: bool,
+}
+
+impl RepeatOptions {
+    pub fn is_repeated(&self) -> bool {
+        self.iterations.is_some() || self.duration.is_some()
+    }
+
+    fn is_done(&self, iterations: u32, elapsed: Duration) -> bool {
+        self.iterations.is_some_and(|n| iterations >= n)
+            || self.duration.is_some_and(|d| elapsed >= d)
+    }
+}
+
+/// Runs `ids` again and again until `options` says to stop, then prints the
+/// share of runs without failure and the failure times of each test. Every
+/// run of a test is an attempt of it in `campaign`, so the report Frequency
+/// column covers the whole soak. Verdicts come from the automated
+/// instructions only.
+pub fn run_test_ids_repeated(
+    campaign: &Campaign,
+    file_path: &str,
+    ids: &[String],
+    excel_path: Option<&str>,
+    options: &RepeatOptions,
+) -> Result<(), Box<dyn Error>> {
+    // Attempts made before this run are left out of the summary
+    let mut first_attempts: HashMap<&str, u32> = HashMap::new();
+    for test_id in ids {
+        first_attempts.insert(test_id, campaign.attempts(test_id)?.len() as u32 + 1);
+    }
+
+    let start = Instant::now();
+    let mut iterations = 0;
+    while !options.is_done(iterations, start.elapsed()) {
+        iterations += 1;
+        print_thick_separator();
+        match options.iterations {
+            Some(n) => println!("Iteration {} of {}", iterations, n),
+            None => println!("Iteration {}", iterations),
+        }
+        if let Some(duration) = options.duration {
+            println!(
+                "Elapsed {} of {}",
+                human_readable_time(start.elapsed().as_secs() as u32),
+                human_readable_time(duration.as_secs() as u32)
+            );
+        }
+
+        let mode = TestRunMode {
+            prompt_verdict: false,
+            unattended: true,
+            stop_on_fail: options.stop_on_fail,
+        };
+        let outcomes = run_test_ids(campaign, file_path, ids, excel_path, mode)?;
+        if options.stop_on_fail && outcomes.values().any(|o| *o == TestOutcome::Failed) {
+            println!("Stopping in iteration {}: a test failed", iterations);
+            break;
+        }
+    }
+
+    print_thick_separator();
+    println!(
+        "Repeat summary: {} iteration(s) in {}",
+        iterations,
+        human_readable_time(start.elapsed().as_secs() as u32)
+    );
+    for test_id in ids {
+        let attempts: Vec<AttemptRecord> = campaign
+            .attempts(test_id)?
+            .into_iter()
+            .filter(|a| a.attempt >= first_attempts[test_id.as_str()])
+            .collect();
+        let ran = attempts.iter().filter(|a| TestOutcome::ran(&a.outcome));
+        let total = ran.clone().count();
+        let succeeded = ran
+            .clone()
+            .filter(|a| a.outcome != TestOutcome::Failed.as_str())
+            .count();
+        let not_verified = ran
+            .filter(|a| a.outcome == TestOutcome::NotVerified.as_str())
+            .count();
+        let not_run = attempts.len() - total;
+
+        print_thin_separator();
+        let rate = if total > 0 {
+            succeeded as f64 * 100.0 / total as f64
+        } else {
+            0.0
+        };
+        print!(
+            "Test '{}': {}/{} without failure ({:.1}%)",
+            test_id, succeeded, total, rate
+        );
+        if not_verified > 0 {
+            print!(", {} not verified", not_verified);
+        }
+        if not_run > 0 {
+            print!(", {} blocked or skipped", not_run);
+        }
+        println!();
+        for a in attempts
+            .iter()
+            .filter(|a| a.outcome == TestOutcome::Failed.as_str())
+        {
+            println!(
+                "  failed at {} (attempt {}): {}",
+                a.timestamp, a.attempt, a.notes
+            );
+        }
+    }
+
+    Ok(())
+}